@@ -0,0 +1,12 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=lib4bin");
+
+    #[cfg(feature = "zstd-lib4bin")]
+    {
+        let lib4bin = std::fs::read("lib4bin").expect("Failed to read lib4bin");
+        let lib4bin_compressed = zstd::encode_all(&lib4bin[..], 19).expect("Failed to compress lib4bin");
+        let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is not set");
+        std::fs::write(format!("{out_dir}/lib4bin.zst"), lib4bin_compressed).expect("Failed to write lib4bin.zst");
+    }
+}
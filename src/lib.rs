@@ -0,0 +1,4056 @@
+use std::{
+    env,
+    str::FromStr,
+    path::{Path, PathBuf},
+    ffi::{CString, OsStr},
+    process::Command,
+    fs::{File, read, write, read_to_string},
+    os::unix::{fs::{MetadataExt, PermissionsExt}, process::CommandExt},
+    io::{Read, Result, Error, Write, BufRead, BufReader, IsTerminal, ErrorKind::{InvalidData, InvalidInput, NotFound}}
+};
+
+use cfg_if::cfg_if;
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+use nix::{
+    unistd::{access, getuid, getgid, fork, setsid, AccessFlags, ForkResult, Pid},
+    sched::{unshare, CloneFlags},
+    mount::{mount, MsFlags},
+    sys::{
+        wait::{waitpid, WaitStatus}, statvfs::{statvfs, FsFlags}, prctl::set_pdeathsig,
+        signal::{kill, sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal}
+    }
+};
+use goblin::elf::{Elf, program_header::PT_INTERP, dynamic::{DT_NEEDED, DT_SONAME, DT_RPATH, DT_RUNPATH}};
+
+
+const SHARUN_NAME: &str = env!("CARGO_PKG_NAME");
+
+
+fn get_default_interpreters() -> Vec<String> {
+    let mut interpreters = Vec::new();
+    #[cfg(target_arch = "x86_64")]          // target x86_64-unknown-linux-musl
+    interpreters.append(&mut vec![
+        "ld-linux-x86-64.so.2".into(),
+        "ld-musl-x86_64.so.1".into(),
+        "ld-linux.so.2".into()
+    ]);
+    #[cfg(target_arch = "aarch64")]         // target aarch64-unknown-linux-musl
+    interpreters.append(&mut vec![
+        "ld-linux-aarch64.so.1".into(),
+        "ld-musl-aarch64.so.1".into()
+    ]);
+    #[cfg(target_arch = "riscv64")]         // target riscv64gc-unknown-linux-musl
+    interpreters.append(&mut vec![
+        "ld-linux-riscv64-lp64d.so.1".into(),
+        "ld-musl-riscv64.so.1".into()
+    ]);
+    #[cfg(target_arch = "loongarch64")]     // target loongarch64-unknown-linux-musl
+    interpreters.append(&mut vec![
+        "ld-linux-loongarch-lp64d.so.1".into(),
+        "ld-musl-loongarch64.so.1".into()
+    ]);
+    interpreters
+}
+
+pub fn get_interpreter(library_path: &str) -> Result<PathBuf> {
+    let mut interpreters = Vec::new();
+    if let Ok(ldname) = env::var("SHARUN_LDNAME") {
+        if !ldname.is_empty() {
+            interpreters.push(ldname)
+        }
+    } else {
+        interpreters = get_default_interpreters()
+    }
+    for interpreter in interpreters {
+        let interpreter_path = Path::new(library_path).join(interpreter);
+        if interpreter_path.exists() {
+            return Ok(interpreter_path)
+        }
+    }
+    Err(Error::last_os_error())
+}
+
+pub fn get_bin_interpreter(bin: &str, library_path: &str) -> Result<PathBuf> {
+    if env::var("SHARUN_LDNAME").is_err() {
+        if let Ok(elf_bytes) = get_elf(bin) {
+            if let Ok(interp) = get_elf_interp(&elf_bytes) {
+                let interpreter_path = Path::new(library_path).join(basename(&interp));
+                if interpreter_path.exists() {
+                    return Ok(interpreter_path)
+                }
+            }
+        }
+    }
+    get_interpreter(library_path)
+}
+
+fn realpath(path: &str) -> Result<String> {
+    Path::new(path).canonicalize()?.to_str()
+        .map(String::from)
+        .ok_or_else(|| Error::new(InvalidData, "path is not valid UTF-8"))
+}
+
+fn basename(path: &str) -> String {
+    let pieces: Vec<&str> = path.rsplit('/').collect();
+    pieces.first().unwrap_or(&"").to_string()
+}
+
+fn dirname(path: &str) -> String {
+    let mut pieces: Vec<&str> = path.split('/').collect();
+    if pieces.len() == 1 || path.is_empty() {
+        // return ".".to_string();
+    } else if !path.starts_with('/') &&
+        !path.starts_with('.') &&
+        !path.starts_with('~') {
+            pieces.insert(0, ".");
+    } else if pieces.len() == 2 && path.starts_with('/') {
+        pieces.insert(0, "");
+    };
+    pieces.pop();
+    pieces.join(&'/'.to_string())
+}
+
+fn is_glob_match(pattern: &str, name: &str) -> bool {
+    glob::Pattern::new(pattern).is_ok_and(|pattern| pattern.matches(name))
+}
+
+fn expand_glob(base_dir: &str, pattern: &str) -> Vec<String> {
+    if !pattern.contains(['*', '?', '[']) {
+        return vec![pattern.into()]
+    }
+    let full_pattern = if pattern.starts_with('/') {
+        pattern.into()
+    } else {
+        format!("{}/{pattern}", glob::Pattern::escape(base_dir))
+    };
+    let mut matched: Vec<String> = glob::glob(&full_pattern).into_iter().flatten().flatten()
+        .map(|path| path.to_string_lossy().into())
+        .collect();
+    if matched.is_empty() {
+        return vec![pattern.into()]
+    }
+    matched.sort();
+    matched
+}
+
+fn is_hardlink(path1: &Path, path2: &Path) -> bool {
+    if let Ok(metadata1) = path1.metadata() {
+        if let Ok(metadata2) = path2.metadata() {
+            return metadata1.ino() == metadata2.ino()
+        }
+    }
+    false
+}
+
+fn is_same_rootdir(rootdir: &Path, path1: &Path, path2: &Path) -> bool {
+    if let Ok(abs_path1) = path1.canonicalize() {
+        if let Ok(abs_path2) = path2.canonicalize() {
+            if let Ok(abs_rootdir) = &rootdir.canonicalize() {
+                return abs_path1.starts_with(abs_rootdir) && abs_path2.starts_with(abs_rootdir)
+            }
+        }
+    }
+    false
+}
+
+fn is_writable(path: &str) -> bool {
+    access(path, AccessFlags::W_OK).is_ok()
+}
+
+fn is_dir(path: &str) -> bool {
+    Path::new(path).is_dir()
+}
+
+fn is_file(path: &Path) -> bool {
+    if let Ok(metadata) = path.metadata() {
+        return metadata.is_file()
+    }
+    false
+}
+
+fn is_exe(path: &Path) -> bool {
+    if let Ok(metadata) = path.metadata() {
+        return metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    false
+}
+
+fn which(executable: &str) -> Option<PathBuf> {
+    if let Ok(path) = env::var("PATH") {
+        for dir in path.split(':') {
+            let full_path = Path::new(dir).join(executable);
+            if is_exe(&full_path) {
+                return Some(full_path)
+            }
+        }
+    }
+    None
+}
+
+fn is_script(path: &Path) -> Result<bool> {
+    let mut file = File::open(path)?;
+    let mut buffer = [0; 2];
+    file.read_exact(&mut buffer)?;
+    Ok(&buffer[0..2] == b"#!")
+}
+
+fn read_first_line(path: &Path) -> Result<String> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    Ok(line)
+}
+
+fn script_command(path: &Path) -> Result<Command> {
+    let first_line = read_first_line(path)?;
+    if !first_line.starts_with("#!") {
+        return Err(Error::new(NotFound, "Script does not have a valid shebang!"))
+    }
+    let shebang = first_line[2..].trim();
+    let parts: Vec<&str> = shebang.split_whitespace().collect();
+    if parts.is_empty() {
+        return Err(Error::new(NotFound, "Invalid shebang: no interpreter specified!"))
+    }
+    let interpreter_path = parts[0];
+    let mut command = if interpreter_path.ends_with("/env") {
+        if parts.len() < 2 {
+            return Err(Error::new(NotFound, "No interpreter specified after env!"))
+        }
+        let interpreter = parts[1];
+        let interpreter_path = match which(interpreter) {
+            Some(path) => path,
+            None => return Err(Error::new(NotFound,
+                format!("Interpreter '{interpreter}' not found in PATH"))
+            )
+        };
+        let mut command = Command::new(&interpreter_path);
+        for arg in &parts[2..] {
+            command.arg(arg);
+        }
+        command
+    } else {
+        let interpreter_name = Path::new(interpreter_path)
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let interpreter_path = match which(&interpreter_name) {
+            Some(path) => path,
+            None => PathBuf::from(interpreter_path)
+        };
+        if !interpreter_path.exists() {
+            return Err(Error::new(NotFound,
+                format!("Interpreter '{}' not found", interpreter_path.display()))
+            )
+        }
+        let mut command = Command::new(&interpreter_path);
+        for arg in &parts[1..] {
+            command.arg(arg);
+        }
+        command
+    };
+    command.arg(path);
+    Ok(command)
+}
+
+fn exec_script(path: &Path, exec_args: &[String]) -> Result<()> {
+    let err = script_command(path)?.args(exec_args).exec();
+    Err(Error::new(InvalidData, err))
+}
+
+fn split_args(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let mut is_arg = false;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+    while let Some(char) = chars.next() {
+        match (quote, char) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                if let Some(next) = chars.next() {
+                    arg.push(next)
+                }
+            }
+            (Some(_), c) => arg.push(c),
+            (None, '\'' | '"') => quote = Some(char),
+            (None, c) if c.is_whitespace() => {
+                if is_arg {
+                    args.push(std::mem::take(&mut arg));
+                    is_arg = false
+                }
+                continue
+            }
+            (None, c) => arg.push(c)
+        }
+        is_arg = true
+    }
+    if is_arg {
+        args.push(arg)
+    }
+    args
+}
+
+fn read_aliases(sharun_dir: &str) -> Result<std::collections::HashMap<String, String>> {
+    let aliases_path = PathBuf::from(format!("{sharun_dir}/aliases"));
+    let mut aliases = std::collections::HashMap::new();
+    if aliases_path.exists() {
+        let data = read_to_string(&aliases_path)
+            .map_err(|err| Error::other(format!("Failed to read aliases file: {}: {err}", aliases_path.display())))?;
+        for string in data.split("\n").map(|string| string.trim()) {
+            if string.starts_with('#') { continue }
+            if let Some((alias, bin)) = string.split_once('=') {
+                aliases.insert(alias.trim().into(), bin.trim().into());
+            }
+        }
+    }
+    Ok(aliases)
+}
+
+fn print_env(key: &str) {
+    if key.is_empty() {
+        let envs: std::collections::BTreeMap<String, String> = env::vars().collect();
+        for (key, val) in envs {
+            println!("{key}={val}")
+        }
+    } else {
+        println!("{}", get_env_var(key))
+    }
+}
+
+fn get_output_lines(command: &mut Command) -> Result<Vec<String>> {
+    match command.output() {
+        Ok(output) => Ok(String::from_utf8_lossy(&output.stdout)
+            .lines().map(String::from).collect()),
+        Err(err) => Err(Error::other(format!("Failed to run: {:?}: {err}", command.get_program())))
+    }
+}
+
+fn compare_bundles(sharun: &Path, sharun_dir: &str, other_dir: &str, bin_name: &str) -> Result<bool> {
+    let mut plans = Vec::new();
+    for (sharun_path, bundle_dir) in [
+        (sharun.to_path_buf(), sharun_dir),
+        (Path::new(other_dir).join(SHARUN_NAME), other_dir)
+    ] {
+        if !is_exe(&sharun_path) {
+            return Err(Error::other(format!("Failed to find sharun: {}", sharun_path.display())))
+        }
+        let mut plan = Vec::new();
+        for print_arg in ["--print-loader-cmd", "--print-env"] {
+            plan.append(&mut get_output_lines(Command::new(&sharun_path)
+                .env_remove("SHARUN_DIR")
+                .args([print_arg, bin_name]))?);
+        }
+        // bundle paths are expected to differ
+        plans.push(plan.iter()
+            .map(|line| line.replace(bundle_dir, "$SHARUN_DIR"))
+            .collect::<Vec<String>>())
+    }
+    let mut is_same = true;
+    for line in plans[0].iter().filter(|line| !plans[1].contains(line)) {
+        println!("- {line}");
+        is_same = false
+    }
+    for line in plans[1].iter().filter(|line| !plans[0].contains(line)) {
+        println!("+ {line}");
+        is_same = false
+    }
+    Ok(is_same)
+}
+
+fn print_env_changes(initial_env: &std::collections::BTreeMap<String, String>) {
+    let current_env: std::collections::BTreeMap<String, String> = env::vars().collect();
+    for (key, val) in &current_env {
+        match initial_env.get(key) {
+            Some(old_val) if old_val == val => {}
+            Some(old_val) => eprintln!("sharun: env: {key}: {old_val} => {val}"),
+            None => eprintln!("sharun: env: {key}: => {val}")
+        }
+    }
+    for key in initial_env.keys().filter(|key| !current_env.contains_key(*key)) {
+        eprintln!("sharun: env: unset {key}")
+    }
+}
+
+type EnvTrace = Option<(std::collections::BTreeMap<String, String>, std::collections::BTreeMap<String, Vec<String>>)>;
+
+fn trace_env(env_trace: &mut EnvTrace, source: &str) {
+    if let Some((snapshot, sources)) = env_trace {
+        let current_env: std::collections::BTreeMap<String, String> = env::vars().collect();
+        for (key, val) in &current_env {
+            if snapshot.get(key) != Some(val) {
+                sources.entry(key.clone()).or_default().push(source.into())
+            }
+        }
+        for key in snapshot.keys().filter(|key| !current_env.contains_key(*key)) {
+            sources.entry(key.clone()).or_default().push(format!("{source} (unset)"))
+        }
+        *snapshot = current_env
+    }
+}
+
+fn print_env_sources(env_trace: EnvTrace) {
+    if let Some((current_env, sources)) = env_trace {
+        for (key, val) in &current_env {
+            println!("{key}={val} [{}]", sources.get(key).map_or("inherited".into(), |source| source.join(", ")))
+        }
+        for (key, source) in sources.iter().filter(|(key, _)| !current_env.contains_key(*key)) {
+            println!("{key} [{}]", source.join(", "))
+        }
+    }
+}
+
+fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c)) {
+        arg.into()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+fn print_command<S: AsRef<str>>(args: &[S]) {
+    println!("{}", args.iter()
+        .map(|arg| shell_quote(arg.as_ref()))
+        .collect::<Vec<String>>()
+        .join(" "))
+}
+
+fn json_string(string: &str) -> String {
+    let mut json = String::from('"');
+    for char in string.chars() {
+        match char {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c)
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(feature = "testing")]
+fn get_plan_json(initial_env: &std::collections::BTreeMap<String, String>,
+    interpreter: &Path, library_path: &str, argv: &[String], mode: &str) -> String {
+    let current_env: std::collections::BTreeMap<String, String> = env::vars().collect();
+    let set_env = current_env.iter()
+        .filter(|(key, val)| initial_env.get(*key) != Some(val))
+        .map(|(key, val)| format!("    {}: {}", json_string(key), json_string(val)))
+        .collect::<Vec<String>>();
+    let unset_env = initial_env.keys()
+        .filter(|key| !current_env.contains_key(*key))
+        .map(|key| format!("    {}", json_string(key)))
+        .collect::<Vec<String>>();
+    let argv = argv.iter()
+        .map(|arg| format!("    {}", json_string(arg)))
+        .collect::<Vec<String>>();
+    format!("{{
+  \"mode\": {},
+  \"interpreter\": {},
+  \"library_path\": {},
+  \"argv\": [\n{}\n  ],
+  \"set_env\": {{\n{}\n  }},
+  \"unset_env\": [\n{}\n  ]
+}}",
+        json_string(mode),
+        json_string(&interpreter.to_string_lossy()),
+        json_string(library_path),
+        argv.join(",\n"),
+        set_env.join(",\n"),
+        unset_env.join(",\n"))
+}
+
+fn print_dry_run(argv: &[String]) {
+    let mut command = vec!["env".into(), "-i".into()];
+    let envs: std::collections::BTreeMap<String, String> = env::vars().collect();
+    command.extend(envs.iter().map(|(key, val)| format!("{key}={val}")));
+    command.extend_from_slice(argv);
+    if let Ok(current_dir) = env::current_dir() {
+        print!("cd {} && ", shell_quote(&current_dir.to_string_lossy()))
+    }
+    print_command(&command)
+}
+
+fn run_stdin_args<F: FnMut() -> Result<Command>>(mut new_command: F) -> Result<i32> {
+    let mut exit_code = 0;
+    for (num, line) in std::io::stdin().lock().lines().map_while(Result::ok).enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue
+        }
+        // the batch lines are read from stdin, the children must not consume them
+        match new_command()?.args(split_args(line)).stdin(std::process::Stdio::null()).status() {
+            Ok(status) => {
+                let code = status.code().unwrap_or(1);
+                eprintln!("[{}] {line}: exit code {code}", num + 1);
+                if code != 0 {
+                    exit_code = code
+                }
+            }
+            Err(err) => {
+                eprintln!("[{}] {line}: failed to run: {err}", num + 1);
+                exit_code = 1
+            }
+        }
+    }
+    Ok(exit_code)
+}
+
+fn get_elf_class(path: &Path) -> Option<u8> {
+    let mut elf_bytes = [0; 5];
+    File::open(path).and_then(|mut file| file.read_exact(&mut elf_bytes)).ok()?;
+    if &elf_bytes[0..4] != b"\x7fELF" {
+        return None
+    }
+    Some(if elf_bytes[4] == 1 { 32 } else { 64 })
+}
+
+fn list_bins_json(bin_dir: &str, shared_bin: &str) {
+    let mut bins = Vec::new();
+    if let Ok(dir) = Path::new(bin_dir).read_dir() {
+        for bin in dir.flatten() {
+            if !is_exe(&bin.path()) {
+                continue
+            }
+            let name = bin.file_name().to_string_lossy().to_string();
+            let shared_bin_path = Path::new(shared_bin).join(&name);
+            let path = if shared_bin_path.exists() { shared_bin_path } else { bin.path() };
+            let elf_class = get_elf_class(&path);
+            cfg_if! {
+                if #[cfg(feature = "pyinstaller")] {
+                    let is_pyinstaller = elf_class.is_some() && get_elf(&path.to_string_lossy())
+                        .and_then(|elf_bytes| is_elf_section(&elf_bytes, "pydata"))
+                        .unwrap_or(false);
+                } else {
+                    let is_pyinstaller = false;
+                }
+            }
+            bins.push(format!("  {{\"name\": {}, \"path\": {}, \"elf_class\": {}, \"is_pyinstaller\": {is_pyinstaller}}}",
+                json_string(&name), json_string(&path.to_string_lossy()),
+                elf_class.map_or("null".into(), |elf_class| elf_class.to_string())))
+        }
+    }
+    bins.sort();
+    if bins.is_empty() {
+        println!("[]")
+    } else {
+        println!("[\n{}\n]", bins.join(",\n"))
+    }
+}
+
+#[cfg(feature = "elf32")]
+pub fn is_elf32(path: &str) -> Result<bool> {
+    let mut elf_bytes = Vec::with_capacity(5);
+    File::open(path)?.take(5).read_to_end(&mut elf_bytes)?;
+    // a truncated file or a script is not an ELF32
+    if elf_bytes.len() < 5 || &elf_bytes[0..4] != b"\x7fELF" {
+        return Ok(false)
+    }
+    Ok(elf_bytes[4] == 1)
+}
+
+#[cfg_attr(not(feature = "elf32"), allow(unused_variables))]
+fn check_elf32(bin: &str) -> Result<bool> {
+    cfg_if! {
+        if #[cfg(feature = "elf32")] {
+            // the inspection commands pick the same lib dir as the launch
+            if get_env_var("SHARUN_FORCE_LIB32") == "1" {
+                Ok(true)
+            } else if get_env_var("SHARUN_FORCE_LIB64") == "1" {
+                Ok(false)
+            } else {
+                is_elf32(bin).map_err(|err| Error::other(format!("Failed to check ELF class: {bin}: {err}")))
+            }
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+pub fn get_elf(path: &str) -> Result<memmap2::Mmap> {
+    let file = File::open(path)?;
+    // the mapping is only read before the file is patched from a copy
+    unsafe { memmap2::Mmap::map(&file) }
+}
+
+#[cfg(feature = "pyinstaller")]
+fn is_elf_section(elf_bytes: &[u8], section_name: &str) -> Result<bool> {
+    if let Ok(elf) = Elf::parse(elf_bytes) {
+        if let Some(section_headers) = elf.section_headers.as_slice().get(..) {
+            for section_header in section_headers {
+                if let Some(name) = elf.shdr_strtab.get_at(section_header.sh_name) {
+                    if name == section_name {
+                        return Ok(true)
+                    }
+                }
+            }
+        }
+    }
+    Ok(false)
+}
+
+fn write_file(elf_path: &str, bytes: &[u8]) -> Result<bool> {
+    let mut file = File::create(elf_path)?;
+    file.write_all(bytes)?;
+    Ok(true)
+}
+
+pub fn set_interp(mut elf_bytes: Vec<u8>, elf_path: &str, new_interp: &str) -> Result<bool> {
+    let elf = Elf::parse(&elf_bytes)
+        .map_err(|err| Error::new(InvalidData, err))?;
+    let interp_header = elf.program_headers.iter().find(|header| header.p_type == PT_INTERP);
+    match interp_header {
+        Some(header) => {
+            let start = header.p_offset as usize;
+            let end = start + header.p_filesz as usize;
+            let interp_slice = &mut elf_bytes[start..end];
+            if interp_slice.last() != Some(&0) {
+                return Err(Error::new(InvalidData, "Current INTERP not NUL terminated"));
+            }
+            if new_interp.len() > (header.p_filesz as usize) - 1 {
+                return Err(Error::new(InvalidData, "Current INTERP too small"));
+            }
+            let new_interp_bytes = new_interp.as_bytes();
+            interp_slice[..new_interp_bytes.len()].copy_from_slice(new_interp_bytes);
+            for byte in interp_slice.iter_mut().take((header.p_filesz as usize) - 1).skip(new_interp_bytes.len()) { *byte = 0 }
+            interp_slice[(header.p_filesz as usize) - 1] = 0;
+            write_file(elf_path, &elf_bytes)?;
+        }
+        None => {
+            return Err(Error::new(InvalidData, "Failed to find PT_INTERP header"));
+        }
+    }
+    Ok(true)
+}
+
+pub fn get_elf_interp(elf_bytes: &[u8]) -> Result<String> {
+    let elf = Elf::parse(elf_bytes)
+        .map_err(|err| Error::new(InvalidData, err))?;
+    let interp = elf.interpreter
+        .ok_or(Error::new(InvalidData, "Failed to find PT_INTERP header"))?;
+    Ok(interp.into())
+}
+
+pub fn get_runpath_slot(elf_bytes: &[u8]) -> Result<(usize, String)> {
+    let elf = Elf::parse(elf_bytes)
+        .map_err(|err| Error::new(InvalidData, err))?;
+    let dynstr = elf.section_headers.iter()
+        .find(|header| elf.shdr_strtab.get_at(header.sh_name) == Some(".dynstr"))
+        .ok_or(Error::new(InvalidData, "Failed to find .dynstr section"))?;
+    let runpath = elf.dynamic.as_ref()
+        .and_then(|dynamic| dynamic.dyns.iter().find(|dyn_entry| {
+            dyn_entry.d_tag == DT_RUNPATH || dyn_entry.d_tag == DT_RPATH
+        }))
+        .ok_or(Error::new(NotFound, "Failed to find RUNPATH/RPATH entry"))?;
+    let current_runpath = elf.dynstrtab.get_at(runpath.d_val as usize).unwrap_or_default();
+    Ok((dynstr.sh_offset as usize + runpath.d_val as usize, current_runpath.into()))
+}
+
+fn is_runpath_shared(elf_bytes: &[u8]) -> Result<bool> {
+    // linkers tail-merge .dynstr, so another name may point into the RUNPATH string
+    let elf = Elf::parse(elf_bytes)
+        .map_err(|err| Error::new(InvalidData, err))?;
+    let dyns = elf.dynamic.as_ref()
+        .map(|dynamic| dynamic.dyns.as_slice())
+        .unwrap_or_default();
+    let Some(runpath_idx) = dyns.iter().position(|dyn_entry| {
+        dyn_entry.d_tag == DT_RUNPATH || dyn_entry.d_tag == DT_RPATH
+    }) else { return Ok(false) };
+    let start = dyns[runpath_idx].d_val as usize;
+    let end = start + elf.dynstrtab.get_at(start).unwrap_or_default().len();
+    let mut offsets: Vec<usize> = dyns.iter().enumerate()
+        .filter(|(idx, dyn_entry)| *idx != runpath_idx &&
+            [DT_NEEDED, DT_SONAME, DT_RPATH, DT_RUNPATH].contains(&dyn_entry.d_tag))
+        .map(|(_, dyn_entry)| dyn_entry.d_val as usize)
+        .collect();
+    offsets.extend(elf.dynsyms.iter().map(|sym| sym.st_name));
+    if let Some(verneed) = &elf.verneed {
+        for need in verneed.iter() {
+            offsets.push(need.vn_file);
+            offsets.extend(need.iter().map(|aux| aux.vna_name))
+        }
+    }
+    if let Some(verdef) = &elf.verdef {
+        for def in verdef.iter() {
+            offsets.extend(def.iter().map(|aux| aux.vda_name))
+        }
+    }
+    Ok(offsets.iter().any(|offset| (start..end).contains(offset)))
+}
+
+pub fn set_runpath(elf_bytes: &mut [u8], new_runpath: &str) -> Result<bool> {
+    let (offset, current_runpath) = get_runpath_slot(elf_bytes)?;
+    if new_runpath.len() > current_runpath.len() {
+        return Err(Error::new(InvalidData, "Current RUNPATH too small"));
+    }
+    if is_runpath_shared(elf_bytes)? {
+        return Err(Error::new(InvalidData, "Current RUNPATH string is shared with other .dynstr entries"));
+    }
+    let runpath_slice = &mut elf_bytes[offset..offset + current_runpath.len()];
+    runpath_slice.fill(0);
+    runpath_slice[..new_runpath.len()].copy_from_slice(new_runpath.as_bytes());
+    Ok(true)
+}
+
+pub fn print_error(msg: &str) {
+    if std::io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none() {
+        eprintln!("\x1b[1;31m{SHARUN_NAME}: error:\x1b[0m {msg}")
+    } else {
+        eprintln!("{SHARUN_NAME}: error: {msg}")
+    }
+}
+
+fn get_env_var<K: AsRef<OsStr>>(key: K) -> String {
+    env::var(key).unwrap_or_default()
+}
+
+fn add_to_env<K: AsRef<OsStr>, V: AsRef<OsStr>>(key: K, val: V) {
+    let (key, val) = (key.as_ref(), val.as_ref().to_str().unwrap_or_default());
+    let old_val = get_env_var(key);
+    if old_val.is_empty() {
+        env::set_var(key, val)
+    } else if old_val != val &&
+      !old_val.starts_with(&format!("{val}:")) &&
+      !old_val.ends_with(&format!(":{val}")) &&
+      !old_val.contains(&format!(":{val}:")) {
+        env::set_var(key, format!("{val}:{old_val}"))
+    }
+}
+
+fn append_to_env<K: AsRef<OsStr>, V: AsRef<OsStr>>(key: K, val: V) {
+    let (key, val) = (key.as_ref(), val.as_ref().to_str().unwrap_or_default());
+    let old_val = get_env_var(key);
+    if old_val.is_empty() {
+        env::set_var(key, val)
+    } else if !old_val.split(':').any(|old| old == val) {
+        env::set_var(key, format!("{old_val}:{val}"))
+    }
+}
+
+fn update_path(prepend_paths: &[String], append_paths: &[String]) {
+    for path in prepend_paths.iter().rev() {
+        add_to_env("PATH", path)
+    }
+    for path in append_paths {
+        append_to_env("PATH", path)
+    }
+}
+
+#[cfg(feature = "config")]
+fn read_config(sharun_dir: &str) -> Result<()> {
+    let config_file = get_env_var("SHARUN_CONFIG");
+    env::remove_var("SHARUN_CONFIG");
+    let config_path = if config_file.is_empty() {
+        PathBuf::from(format!("{sharun_dir}/sharun.toml"))
+    } else {
+        PathBuf::from(&config_file)
+    };
+    if config_file.is_empty() && !config_path.exists() {
+        return Ok(())
+    }
+    let data = read_to_string(&config_path)
+        .map_err(|err| Error::other(format!("Failed to read config file: {}: {err}", config_path.display())))?;
+    let config = data.parse::<toml::Table>()
+        .map_err(|err| Error::other(format!("Failed to parse config file: {}: {err}", config_path.display())))?;
+    for (key, value) in config {
+        let var_name = format!("SHARUN_{}", key.to_uppercase());
+        let value = match value {
+            toml::Value::String(string) => string,
+            toml::Value::Boolean(bool) => if bool { "1" } else { "0" }.into(),
+            toml::Value::Integer(int) => int.to_string(),
+            toml::Value::Float(float) => float.to_string(),
+            toml::Value::Array(array) => array.iter()
+                .map(|value| value.as_str().map(String::from).unwrap_or_else(|| value.to_string()))
+                .collect::<Vec<String>>()
+                .join(":"),
+            _ => {
+                eprintln!("WARNING: Unsupported config value type: {key}: {}", config_path.display());
+                continue
+            }
+        };
+        if env::var_os(&var_name).is_none() {
+            env::set_var(var_name, value)
+        }
+    }
+    Ok(())
+}
+
+fn expand_env_vars(value: &str) -> String {
+    let mut expanded = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(char) = chars.next() {
+        if char != '$' {
+            expanded.push(char);
+            continue
+        }
+        let mut var_name = String::new();
+        if chars.peek() == Some(&'{') {
+            chars.next();
+            for char in chars.by_ref() {
+                if char == '}' { break }
+                var_name.push(char)
+            }
+        } else {
+            while let Some(&char) = chars.peek() {
+                if !(char.is_ascii_alphanumeric() || char == '_') { break }
+                var_name.push(char);
+                chars.next();
+            }
+            if var_name.is_empty() {
+                expanded.push('$');
+                continue
+            }
+        }
+        expanded.push_str(&get_env_var(&var_name))
+    }
+    expanded
+}
+
+fn strip_inline_comment(string: &str) -> &str {
+    let mut quote = None;
+    let mut prev_char = ' ';
+    for (idx, char) in string.char_indices() {
+        match (quote, char) {
+            (None, '"' | '\'') => quote = Some(char),
+            (Some(quote_char), _) if char == quote_char => quote = None,
+            (None, '#') if prev_char.is_whitespace() => return string[..idx].trim_end(),
+            _ => {}
+        }
+        prev_char = char
+    }
+    string
+}
+
+pub fn read_dotenv(dotenv_dir: &str) -> Result<Vec<String>> {
+    let mut unset_envs = Vec::new();
+    let dotenv_path = PathBuf::from(format!("{dotenv_dir}/.env"));
+    if dotenv_path.exists() {
+        let data = read_to_string(&dotenv_path)
+            .map_err(|err| Error::other(format!("Failed to read .env file: {}: {err}", dotenv_path.display())))?;
+        let mut dotenv_data = String::new();
+        for string in data.trim().split("\n") {
+            let string = string.trim();
+            if string.is_empty() || string.starts_with("#") {
+                continue
+            }
+            if string.starts_with("unset ") {
+                for var_name in string.split_whitespace().skip(1) {
+                    unset_envs.push(var_name.into());
+                }
+                continue
+            }
+            let string = string.strip_prefix("export ").unwrap_or(string).trim_start();
+            dotenv_data.push_str(strip_inline_comment(string));
+            dotenv_data.push('\n')
+        }
+        for (key, value) in dotenv::from_read_iter(dotenv_data.as_bytes()).flatten() {
+            if env::var_os(&key).is_none() {
+                env::set_var(key, expand_env_vars(&value))
+            }
+        }
+    }
+    Ok(unset_envs)
+}
+
+#[cfg(feature = "setenv")]
+fn add_to_xdg_data_env(xdg_data_dirs: &str, env: &str, path: &str) {
+    for xdg_data_dir in xdg_data_dirs.rsplit(":") {
+        let env_data_dir = Path::new(xdg_data_dir).join(path);
+        if env_data_dir.exists() {
+            add_to_env(env, env_data_dir)
+        }
+    }
+}
+
+fn log_scan_err<T, E: std::fmt::Display>(path: &Path, result: std::result::Result<T, E>) -> Option<T> {
+    match result {
+        Ok(val) => Some(val),
+        Err(err) => {
+            if get_env_var("SHARUN_LOG") == "1" {
+                eprintln!("WARNING: Failed to scan: {}: {err}", path.display())
+            }
+            None
+        }
+    }
+}
+
+#[cfg(feature = "setenv")]
+fn check_bundle_dir(path: &Path) -> Result<bool> {
+    if path.is_symlink() && !path.is_dir() {
+        let is_strict = get_env_var("SHARUN_STRICT") == "1";
+        if is_strict || get_env_var("SHARUN_LOG") == "1" {
+            eprintln!("{}: Symlink does not point to a directory: {} -> {}",
+                if is_strict { "ERROR" } else { "WARNING" }, path.display(),
+                path.read_link().unwrap_or_default().display());
+            if is_strict {
+                return Err(Error::other(format!("Symlink does not point to a directory: {}", path.display())))
+            }
+        }
+        return Ok(false)
+    }
+    Ok(path.is_dir())
+}
+
+fn read_lib_path_exclude(library_path: &str) -> Vec<String> {
+    read_to_string(format!("{library_path}/.lib-path-exclude")).unwrap_or_default()
+        .lines()
+        .map(|string| string.trim().trim_matches('/').to_string())
+        .filter(|string| !string.is_empty() && !string.starts_with('#'))
+        .collect()
+}
+
+fn is_lib_path_excluded(path: &Path, library_path: &str, exclude: &[String]) -> bool {
+    let rel_path = path.strip_prefix(library_path).unwrap_or(path).to_string_lossy();
+    let name = basename(&rel_path);
+    exclude.iter().any(|pattern| is_glob_match(pattern, if pattern.contains('/') { &rel_path } else { &name }))
+}
+
+fn is_lib_file(name: &str) -> bool {
+    name.ends_with(".so") || name.contains(".so.")
+}
+
+fn find_lib_dirs(sub_dir: &Path, library_path: &str, exclude: &[String]) -> Vec<String> {
+    let mut lib_dirs: Vec<String> = Vec::new();
+    let skip_dirs = ["lib-dynload".to_string()];
+    WalkDir::new(sub_dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| !entry.file_type().is_dir() ||
+            !is_lib_path_excluded(entry.path(), library_path, exclude))
+        .filter_map(|entry| log_scan_err(sub_dir, entry))
+        .for_each(|entry| {
+            if is_lib_file(&entry.file_name().to_string_lossy()) {
+                if let Some(parent) = entry.path().parent() {
+                    if let Some(parent_str) = parent.to_str() {
+                        if parent_str != library_path && parent.is_dir() &&
+                            !lib_dirs.contains(&parent_str.into()) &&
+                            !skip_dirs.contains(&basename(parent_str)) {
+                            lib_dirs.push(parent_str.into());
+                        }
+                    }
+                }
+            }
+        });
+    lib_dirs
+}
+
+pub fn get_library_path_data(library_path: &str) -> String {
+    let library_dir = Path::new(library_path);
+    // top-level dirs are walked in parallel, sorted for a stable lib.path order
+    let mut sub_dirs: Vec<PathBuf> = log_scan_err(library_dir, library_dir.read_dir())
+        .map(|dir| dir.filter_map(|entry| log_scan_err(library_dir, entry))
+            .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+            .map(|entry| entry.path())
+            .collect())
+        .unwrap_or_default();
+    sub_dirs.sort();
+    let exclude = &read_lib_path_exclude(library_path);
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let chunk_size = sub_dirs.len().div_ceil(threads).max(1);
+    let new_paths: Vec<String> = std::thread::scope(|scope| {
+        let workers: Vec<_> = sub_dirs.chunks(chunk_size).map(|chunk| {
+            scope.spawn(move || chunk.iter()
+                .flat_map(|sub_dir| find_lib_dirs(sub_dir, library_path, exclude))
+                .collect::<Vec<String>>())
+        }).collect();
+        let mut new_paths: Vec<String> = Vec::new();
+        for worker in workers {
+            for lib_dir in worker.join().unwrap_or_default() {
+                if !new_paths.contains(&lib_dir) {
+                    new_paths.push(lib_dir)
+                }
+            }
+        }
+        new_paths
+    });
+    format!("+:{}", &new_paths.join(":"))
+        .replace(":", "\n")
+        .replace(library_path, "+")
+}
+
+fn get_library_path_stamp(library_path: &str, lib_path_data: &str) -> i64 {
+    // a full walk costs as much as the scan, so only the top-level dirs and the dirs from lib.path
+    let library_dir = Path::new(library_path);
+    let mtime = |path: &Path| path.metadata().map(|metadata| metadata.mtime()).unwrap_or_default();
+    let mut stamp = mtime(library_dir).max(mtime(&library_dir.join(".lib-path-exclude")));
+    for entry in library_dir.read_dir().into_iter().flatten().flatten() {
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            stamp = stamp.max(mtime(&entry.path()))
+        }
+    }
+    for string in lib_path_data.split(['\n', ':']).map(|string| string.trim()) {
+        if string.is_empty() { continue }
+        let lib_dir = string.replacen("+", library_path, 1);
+        if !is_dir(&lib_dir) {
+            return i64::MAX
+        }
+        stamp = stamp.max(mtime(Path::new(&lib_dir)))
+    }
+    stamp
+}
+
+fn is_library_path_stale(library_path: &str, lib_path_file: &str) -> bool {
+    let Ok(lib_path_data) = read_to_string(lib_path_file) else { return true };
+    // lib.path shipped without the stamp file is checked against its own mtime
+    let lib_path_stamp = read_to_string(format!("{lib_path_file}.stamp")).ok()
+        .and_then(|stamp| stamp.trim().parse().ok())
+        .unwrap_or_else(|| Path::new(lib_path_file).metadata().map(|metadata| metadata.mtime()).unwrap_or_default());
+    get_library_path_stamp(library_path, &lib_path_data) > lib_path_stamp
+}
+
+pub fn gen_library_path(library_path: &str, lib_path_file: &str) -> Result<()> {
+    let lib_path_data = get_library_path_data(library_path);
+    write(lib_path_file, &lib_path_data)
+        .map_err(|err| Error::other(format!("Failed to write lib.path: {lib_path_file}: {err}")))?;
+    eprintln!("Write lib.path: {lib_path_file}");
+    // the stamp is taken after both files exist, creating them changes the dir mtime
+    let lib_path_stamp_file = &format!("{lib_path_file}.stamp");
+    if !Path::new(lib_path_stamp_file).exists() {
+        write(lib_path_stamp_file, "").ok();
+    }
+    if let Err(err) = write(lib_path_stamp_file, get_library_path_stamp(library_path, &lib_path_data).to_string()) {
+        eprintln!("WARNING: Failed to write lib.path stamp: {lib_path_stamp_file}: {err}")
+    }
+    Ok(())
+}
+
+fn migrate_library_path(library_path: &str, lib_path_file: &str) -> Result<()> {
+    let lib_path_data = read_to_string(lib_path_file)
+        .map_err(|err| Error::other(format!("Failed to read lib.path: {lib_path_file}: {err}")))?;
+    let mut new_paths: Vec<String> = Vec::new();
+    for string in lib_path_data.split(['\n', ':']).map(|string| string.trim()) {
+        if string.is_empty() { continue }
+        let lib_dir = string.replacen("+", library_path, 1);
+        if !is_dir(&lib_dir) {
+            println!("Remove: {string}");
+            continue
+        }
+        let new_path = lib_dir.replacen(library_path, "+", 1);
+        if new_paths.contains(&new_path) {
+            println!("Remove duplicate: {string}")
+        } else {
+            if new_path != string {
+                println!("Normalize: {string} => {new_path}")
+            }
+            new_paths.push(new_path)
+        }
+    }
+    if !new_paths.contains(&"+".into()) {
+        new_paths.insert(0, "+".into())
+    }
+    let new_lib_path_data = new_paths.join("\n");
+    if new_lib_path_data == lib_path_data.trim() {
+        println!("Unchanged: {lib_path_file}");
+        return Ok(())
+    }
+    write(lib_path_file, new_lib_path_data)
+        .map_err(|err| Error::other(format!("Failed to write lib.path: {lib_path_file}: {err}")))?;
+    println!("Write lib.path: {lib_path_file}");
+    Ok(())
+}
+
+#[cfg(feature = "setenv")]
+fn is_file_in_dir<F: Fn(&str) -> bool>(dir: &str, is_match: F) -> bool {
+    WalkDir::new(dir).into_iter().flatten().any(|entry| {
+        is_file(entry.path()) && is_match(&entry.file_name().to_string_lossy())
+    })
+}
+
+#[cfg(feature = "setenv")]
+struct LibRuleEnv {
+    key: String,
+    val: String,
+    is_add: bool,
+    is_if_exists: bool
+}
+
+#[cfg(feature = "setenv")]
+struct LibRule {
+    pattern: String,
+    is_prefix: bool,
+    envs: Vec<LibRuleEnv>
+}
+
+#[cfg(feature = "setenv")]
+const DEFAULT_LIB_RULES: &str = "\
+prefix=perl ; add_env=PERLLIB:$dir
+name=gconv ; add_env=GCONV_PATH:$dir
+name=gio ; env?=GIO_MODULE_DIR:$dir/modules
+name=libheif ; env=LIBHEIF_PLUGIN_PATH:$dir ; env?=LIBHEIF_PLUGIN_PATH:$dir/plugins
+name=xtables ; env=XTABLES_LIBDIR:$dir
+prefix=spa- ; env=SPA_PLUGIN_DIR:$dir
+prefix=pipewire- ; env=PIPEWIRE_MODULE_DIR:$dir
+prefix=libcanberra- ; add_env=LTDL_LIBRARY_PATH:$dir
+name=imlib2 ; env?=IMLIB2_LOADER_PATH:$dir/loaders ; env?=IMLIB2_FILTER_PATH:$dir/filters
+prefix=babl- ; env=BABL_PATH:$dir
+prefix=gegl- ; env=GEGL_PATH:$dir
+name=libdecor ; env?=LIBDECOR_PLUGIN_DIR:$dir/plugins-1
+prefix=gstreamer- ; add_env=GST_PLUGIN_PATH:$dir ; add_env=GST_PLUGIN_SYSTEM_PATH:$dir ; \
+add_env=GST_PLUGIN_SYSTEM_PATH_1_0:$dir ; env?=GST_PLUGIN_SCANNER:$dir/gst-plugin-scanner
+";
+
+#[cfg(feature = "setenv")]
+fn parse_lib_rules(data: &str, source: &str) -> Vec<LibRule> {
+    let mut lib_rules = Vec::new();
+    for string in data.split("\n").map(|string| string.trim()) {
+        if string.is_empty() || string.starts_with('#') { continue }
+        let mut rule = LibRule { pattern: "".into(), is_prefix: false, envs: Vec::new() };
+        for field in string.split(';').map(|field| field.trim()) {
+            match field.split_once('=') {
+                Some(("prefix", pattern)) => {
+                    rule.pattern = pattern.trim().into();
+                    rule.is_prefix = true
+                }
+                Some(("name", pattern)) => rule.pattern = pattern.trim().into(),
+                Some((kind @ ("env" | "add_env" | "env?" | "add_env?"), env)) if env.contains(':') => {
+                    let (key, val) = env.split_once(':').unwrap_or_default();
+                    rule.envs.push(LibRuleEnv {
+                        key: key.trim().into(),
+                        val: val.trim().into(),
+                        is_add: kind.starts_with("add_env"),
+                        is_if_exists: kind.ends_with('?')
+                    })
+                }
+                _ => eprintln!("WARNING: Invalid lib.rules field: {source}: {field}")
+            }
+        }
+        if !rule.pattern.is_empty() && !rule.envs.is_empty() {
+            lib_rules.push(rule)
+        }
+    }
+    lib_rules
+}
+
+#[cfg(feature = "setenv")]
+fn read_lib_rules(library_path: &str) -> Result<Vec<LibRule>> {
+    let rules_path = PathBuf::from(format!("{library_path}/lib.rules"));
+    let mut lib_rules = parse_lib_rules(DEFAULT_LIB_RULES, "built-in");
+    if !rules_path.exists() {
+        return Ok(lib_rules)
+    }
+    let data = read_to_string(&rules_path)
+        .map_err(|err| Error::other(format!("Failed to read lib.rules file: {}: {err}", rules_path.display())))?;
+    // a lib.rules rule for the same dir replaces the built-in one
+    let user_rules = parse_lib_rules(&data, &rules_path.to_string_lossy());
+    lib_rules.retain(|rule| !user_rules.iter().any(|user_rule| {
+        user_rule.pattern == rule.pattern && user_rule.is_prefix == rule.is_prefix
+    }));
+    lib_rules.extend(user_rules);
+    Ok(lib_rules)
+}
+
+#[cfg(feature = "setenv")]
+fn is_icon_cache_valid(theme_dir: &Path, cache_theme_dir: &Path) -> bool {
+    let mtime = |path: &Path| path.metadata().and_then(|metadata| metadata.modified()).ok();
+    match (mtime(&cache_theme_dir.join("icon-theme.cache")), mtime(theme_dir)) {
+        (Some(cache_mtime), Some(dir_mtime)) => cache_mtime >= dir_mtime,
+        _ => false
+    }
+}
+
+#[cfg(feature = "setenv")]
+fn update_icon_cache(sharun_dir: &str, bin_dir: &str, icons_dir: &Path) -> Option<PathBuf> {
+    // gtk-update-icon-cache is launched through sharun too
+    if get_env_var("SHARUN_ICON_CACHE_UPDATE") == "1" {
+        return None
+    }
+    let stale_themes: Vec<PathBuf> = icons_dir.read_dir().ok()?.flatten()
+        .map(|entry| entry.path())
+        .filter(|theme_dir| theme_dir.join("index.theme").is_file() &&
+            !is_icon_cache_valid(theme_dir, theme_dir))
+        .collect();
+    if stale_themes.is_empty() {
+        return None
+    }
+    let update_icon_cache = Path::new(bin_dir).join("gtk-update-icon-cache");
+    if !is_exe(&update_icon_cache) {
+        // most bundles ship icons without the tool, gtk falls back to scanning the theme dirs
+        if get_env_var("SHARUN_LOG") == "1" {
+            eprintln!("WARNING: Icon theme cache is missing or stale and gtk-update-icon-cache is not bundled: {}",
+                icons_dir.display());
+        }
+        return None
+    }
+    let cache_share_dir = get_cache_dir(sharun_dir).join("share");
+    for theme_dir in stale_themes {
+        let cache_theme_dir = cache_share_dir.join("icons").join(theme_dir.file_name()?);
+        if let Err(err) = std::fs::create_dir_all(&cache_theme_dir) {
+            eprintln!("WARNING: Failed to create icon cache dir: {}: {err}", cache_theme_dir.display());
+            return None
+        }
+        // the links are kept in sync with the bundle, an AppImage is mounted to a new dir on every run
+        for entry in theme_dir.read_dir().into_iter().flatten().flatten() {
+            let cache_entry = cache_theme_dir.join(entry.file_name());
+            if entry.file_name() != "icon-theme.cache" &&
+                cache_entry.read_link().ok() != Some(entry.path()) {
+                std::fs::remove_file(&cache_entry).ok();
+                std::os::unix::fs::symlink(entry.path(), cache_entry).ok();
+            }
+        }
+        if is_icon_cache_valid(&theme_dir, &cache_theme_dir) {
+            continue
+        }
+        match Command::new(&update_icon_cache)
+            .env("SHARUN_ICON_CACHE_UPDATE", "1").args(["-q", "-f", "-t"]).arg(&cache_theme_dir).status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => eprintln!("WARNING: gtk-update-icon-cache: {}: {status}", theme_dir.display()),
+            Err(err) => eprintln!("WARNING: Failed to run gtk-update-icon-cache: {err}")
+        }
+    }
+    Some(cache_share_dir)
+}
+
+#[cfg(feature = "setenv")]
+fn is_loaders_cache_valid(loaders_cache: &Path, lib_dir: &Path) -> bool {
+    // relative loader paths are left to gdk-pixbuf
+    read_to_string(loaders_cache).is_ok_and(|data| data.lines()
+        .filter_map(|line| line.strip_prefix('"')?.split('"').next())
+        .map(Path::new)
+        .filter(|path| path.is_absolute() && path.extension().is_some_and(|ext| ext == "so"))
+        .all(|path| path.starts_with(lib_dir) && path.exists()))
+}
+
+#[cfg(feature = "setenv")]
+fn update_loaders_cache(sharun_dir: &str, bin_dir: &str, lib_dir: &Path, loaders_dir: &Path) -> Option<PathBuf> {
+    // gdk-pixbuf-query-loaders is launched through sharun too
+    if get_env_var("SHARUN_LOADERS_CACHE_UPDATE") == "1" {
+        return None
+    }
+    let cache_loaders_cache = get_cache_dir(sharun_dir).join(lib_dir.parent()?.file_name()?)
+        .join(lib_dir.file_name()?).join("loaders.cache");
+    let mtime = |path: &Path| path.metadata().map(|metadata| metadata.mtime()).unwrap_or_default();
+    if mtime(&cache_loaders_cache) >= mtime(loaders_dir) && is_loaders_cache_valid(&cache_loaders_cache, lib_dir) {
+        return Some(cache_loaders_cache)
+    }
+    let query_loaders = Path::new(bin_dir).join("gdk-pixbuf-query-loaders");
+    if !is_exe(&query_loaders) {
+        eprintln!("WARNING: Pixbuf loaders cache has stale paths and gdk-pixbuf-query-loaders is not bundled: {}",
+            loaders_dir.display());
+        return None
+    }
+    let mut loaders: Vec<PathBuf> = loaders_dir.read_dir().ok()?.flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "so"))
+        .collect();
+    loaders.sort();
+    match Command::new(&query_loaders).env("SHARUN_LOADERS_CACHE_UPDATE", "1").args(&loaders).output() {
+        Ok(output) if output.status.success() => {
+            if let Err(err) = std::fs::create_dir_all(cache_loaders_cache.parent()?)
+                .and_then(|_| write(&cache_loaders_cache, output.stdout)) {
+                eprintln!("WARNING: Failed to write pixbuf loaders cache: {}: {err}", cache_loaders_cache.display());
+                return None
+            }
+            Some(cache_loaders_cache)
+        }
+        Ok(output) => {
+            eprintln!("WARNING: gdk-pixbuf-query-loaders: {}: {}", loaders_dir.display(), output.status);
+            None
+        }
+        Err(err) => {
+            eprintln!("WARNING: Failed to run gdk-pixbuf-query-loaders: {err}");
+            None
+        }
+    }
+}
+
+#[cfg(feature = "setenv")]
+fn compile_schemas(sharun_dir: &str, bin_dir: &str, schemas_dir: &Path) -> Option<PathBuf> {
+    // glib-compile-schemas is launched through sharun too
+    if get_env_var("SHARUN_SCHEMAS_COMPILE") == "1" || schemas_dir.join("gschemas.compiled").exists() {
+        return None
+    }
+    let mtime = |path: &Path| path.metadata().map(|metadata| metadata.mtime()).unwrap_or_default();
+    let schemas_mtime = schemas_dir.read_dir().ok()?.flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(".gschema.xml"))
+        .map(|entry| mtime(&entry.path()))
+        .max()?;
+    let target_dir = if is_writable(&schemas_dir.to_string_lossy()) {
+        schemas_dir.to_path_buf()
+    } else {
+        get_cache_dir(sharun_dir).join("share/glib-2.0/schemas")
+    };
+    let compiled = target_dir.join("gschemas.compiled");
+    if compiled.exists() && mtime(&compiled) >= schemas_mtime {
+        return Some(target_dir)
+    }
+    let compile_schemas = Path::new(bin_dir).join("glib-compile-schemas");
+    if !is_exe(&compile_schemas) {
+        eprintln!("WARNING: GSettings schemas are not compiled and glib-compile-schemas is not bundled: {}",
+            schemas_dir.display());
+        return None
+    }
+    if let Err(err) = std::fs::create_dir_all(&target_dir) {
+        eprintln!("WARNING: Failed to create schemas dir: {}: {err}", target_dir.display());
+        return None
+    }
+    match Command::new(&compile_schemas)
+        .env("SHARUN_SCHEMAS_COMPILE", "1").arg("--targetdir").arg(&target_dir).arg(schemas_dir).status() {
+        Ok(status) if status.success() => Some(target_dir),
+        Ok(status) => {
+            eprintln!("WARNING: glib-compile-schemas: {}: {status}", schemas_dir.display());
+            None
+        }
+        Err(err) => {
+            eprintln!("WARNING: Failed to run glib-compile-schemas: {err}");
+            None
+        }
+    }
+}
+
+#[cfg(feature = "setenv")]
+fn gen_fonts_conf(sharun_dir: &str, fonts_conf: &Path) -> Option<PathBuf> {
+    // the bundled fonts.conf is included from a wrapper with a writable cache dir first,
+    // so that the font cache can be generated even if the bundle is read-only
+    let cache_fonts_dir = get_cache_dir(sharun_dir).join("fontconfig");
+    let cache_fonts_conf = cache_fonts_dir.join("fonts.conf");
+    let data = format!("<?xml version=\"1.0\"?>
+<!DOCTYPE fontconfig SYSTEM \"urn:fontconfig:fonts.dtd\">
+<fontconfig>
+  <cachedir>{}</cachedir>
+  <include ignore_missing=\"yes\">{}</include>
+</fontconfig>
+", cache_fonts_dir.join("cache").display(), fonts_conf.display());
+    if read_to_string(&cache_fonts_conf).is_ok_and(|cache_data| cache_data == data) {
+        return Some(cache_fonts_conf)
+    }
+    if let Err(err) = std::fs::create_dir_all(&cache_fonts_dir).and_then(|_| write(&cache_fonts_conf, data)) {
+        eprintln!("WARNING: Failed to write fontconfig config: {}: {err}", cache_fonts_conf.display());
+        return None
+    }
+    Some(cache_fonts_conf)
+}
+
+#[cfg(feature = "setenv")]
+fn collect_json_files(dir: &Path) -> Vec<PathBuf> {
+    let mut json_paths = Vec::new();
+    if dir.exists() {
+        if let Ok(entries) = dir.read_dir() {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().is_some_and(|ext| ext == "json") &&
+                   path.exists() { json_paths.push(path) }
+            }
+        }
+    }
+    json_paths
+}
+
+fn get_cache_dir(sharun_dir: &str) -> PathBuf {
+    let cache_dir = get_env_var("SHARUN_CACHE_DIR");
+    if !cache_dir.is_empty() {
+        return PathBuf::from(cache_dir)
+    }
+    let mut xdg_cache_home = get_env_var("XDG_CACHE_HOME");
+    if xdg_cache_home.is_empty() {
+        xdg_cache_home = format!("{}/.cache", get_env_var("HOME"))
+    }
+    // an AppImage is mounted to a new dir on every run, so the key is the bundle and not its mount point
+    let mut hasher = Sha256::new();
+    let appimage = get_env_var("APPIMAGE");
+    if !appimage.is_empty() {
+        hasher.update(appimage.as_bytes())
+    } else if let Ok(manifest_data) = read(format!("{sharun_dir}/.manifest")) {
+        hasher.update(&manifest_data)
+    } else {
+        hasher.update(sharun_dir.as_bytes())
+    }
+    let bundle_hash = format!("{:x}", hasher.finalize());
+    PathBuf::from(xdg_cache_home).join(SHARUN_NAME).join(&bundle_hash[..16])
+}
+
+fn get_lib_path_file(sharun_dir: &str, library_path: &str) -> String {
+    let lib_path_file = format!("{library_path}/lib.path");
+    if Path::new(&lib_path_file).exists() || is_writable(library_path) {
+        lib_path_file
+    } else {
+        get_cache_dir(sharun_dir).join(basename(library_path)).join("lib.path")
+            .to_string_lossy().to_string()
+    }
+}
+
+pub fn get_lib_dirs(sharun_dir: &str, library_path: &str) -> Vec<String> {
+    let lib_path_data = read_to_string(get_lib_path_file(sharun_dir, library_path)).unwrap_or_default();
+    let mut lib_dirs: Vec<String> = lib_path_data.trim().split("\n")
+        .filter(|string| !string.is_empty())
+        .map(|string| string.replacen("+", library_path, 1))
+        .collect();
+    if !lib_dirs.contains(&library_path.into()) {
+        lib_dirs.insert(0, library_path.into())
+    }
+    lib_dirs
+}
+
+pub fn get_needed_libs(path: &Path) -> Result<Vec<String>> {
+    get_elf_needed_libs(&get_elf(&path.to_string_lossy())?)
+}
+
+fn get_elf_needed_libs(elf_bytes: &[u8]) -> Result<Vec<String>> {
+    let elf = Elf::parse(elf_bytes)
+        .map_err(|err| Error::new(InvalidData, err))?;
+    Ok(elf.libraries.iter().map(|lib| lib.to_string()).collect())
+}
+
+pub fn find_lib(lib_dirs: &[String], lib_name: &str) -> Option<PathBuf> {
+    lib_dirs.iter()
+        .map(|lib_dir| Path::new(lib_dir).join(lib_name))
+        .find(|lib_path| lib_path.exists())
+}
+
+struct DepNode {
+    name: String,
+    path: Option<PathBuf>,
+    // resolved earlier in the walk, its deps are listed only there
+    is_seen: bool,
+    deps: Vec<DepNode>,
+}
+
+fn resolve_deps(path: &Path, lib_dirs: &[String], resolved: &mut Vec<PathBuf>, is_ok: &mut bool) -> Vec<DepNode> {
+    let needed_libs = get_needed_libs(path).unwrap_or_else(|err|{
+        eprintln!("Failed to read ELF: {}: {err}", path.display());
+        *is_ok = false;
+        vec![]
+    });
+    let mut deps = Vec::new();
+    for name in needed_libs {
+        let path = find_lib(lib_dirs, &name);
+        let is_seen = path.as_ref().is_some_and(|lib_path| resolved.contains(lib_path));
+        let mut lib_deps = Vec::new();
+        if let Some(lib_path) = path.as_ref().filter(|_| !is_seen) {
+            resolved.push(lib_path.clone());
+            lib_deps = resolve_deps(lib_path, lib_dirs, resolved, is_ok)
+        }
+        deps.push(DepNode { name, path, is_seen, deps: lib_deps })
+    }
+    deps
+}
+
+fn get_dep_tree(path: &Path, lib_dirs: &[String]) -> (Vec<DepNode>, bool) {
+    fn is_found(deps: &[DepNode]) -> bool {
+        deps.iter().all(|dep| dep.path.is_some() && is_found(&dep.deps))
+    }
+    let mut is_ok = true;
+    let deps = resolve_deps(path, lib_dirs, &mut Vec::new(), &mut is_ok);
+    let is_resolved = is_ok && is_found(&deps);
+    (deps, is_resolved)
+}
+
+fn print_trace_deps(deps: &[DepNode], depth: usize) {
+    let indent = "    ".repeat(depth);
+    for dep in deps {
+        match &dep.path {
+            Some(lib_path) if dep.is_seen => println!("{indent}{} => {} (see above)", dep.name, lib_path.display()),
+            Some(lib_path) => {
+                println!("{indent}{} => {}", dep.name, lib_path.display());
+                print_trace_deps(&dep.deps, depth + 1)
+            }
+            None => println!("{indent}{} => not found", dep.name)
+        }
+    }
+}
+
+fn print_missing_deps(path: &Path, deps: &[DepNode]) {
+    for dep in deps {
+        match &dep.path {
+            Some(lib_path) => print_missing_deps(lib_path, &dep.deps),
+            None => println!("Missing: {} (needed by {})", dep.name, path.display())
+        }
+    }
+}
+
+fn print_ldd_deps(deps: &[DepNode]) {
+    // breadth first like the loader, each soname once
+    let mut listed: Vec<&str> = Vec::new();
+    let mut level: Vec<&DepNode> = deps.iter().collect();
+    while !level.is_empty() {
+        for dep in &level {
+            if listed.contains(&dep.name.as_str()) { continue }
+            listed.push(&dep.name);
+            match &dep.path {
+                Some(lib_path) => println!("\t{} => {}", dep.name, lib_path.display()),
+                None => println!("\t{} => not found", dep.name)
+            }
+        }
+        level = level.iter().flat_map(|dep| &dep.deps).collect();
+    }
+}
+
+fn json_deps(deps: &[DepNode], depth: usize) -> String {
+    let indent = "  ".repeat(depth);
+    // already traced libraries are listed without children
+    let children: Vec<String> = deps.iter().map(|dep| {
+        format!("{indent}  {{\"soname\": {}, \"path\": {}, \"children\": {}}}",
+            json_string(&dep.name),
+            dep.path.as_ref().map_or("null".into(), |lib_path| json_string(&lib_path.to_string_lossy())),
+            json_deps(&dep.deps, depth + 2))
+    }).collect();
+    if children.is_empty() {
+        "[]".into()
+    } else {
+        format!("[\n{}\n{indent}]", children.join(",\n"))
+    }
+}
+
+fn get_relative_path(from_dir: &Path, to_dir: &Path) -> PathBuf {
+    let from_components: Vec<_> = from_dir.components().collect();
+    let to_components: Vec<_> = to_dir.components().collect();
+    let common_count = from_components.iter().zip(&to_components)
+        .take_while(|(from, to)| from == to).count();
+    let mut relative_path = PathBuf::new();
+    for _ in common_count..from_components.len() {
+        relative_path.push("..")
+    }
+    for component in &to_components[common_count..] {
+        relative_path.push(component)
+    }
+    relative_path
+}
+
+fn gen_bins(sharun: &Path, bin_dir: &str, shared_bin: &str, exclude: &[&str]) -> Result<()> {
+    std::fs::create_dir_all(bin_dir)?;
+    // relative link keeps the bundle relocatable
+    let sharun_link = get_relative_path(Path::new(bin_dir), sharun.parent().unwrap_or(Path::new("/")))
+        .join(sharun.file_name().unwrap_or_default());
+    let mut bins: Vec<PathBuf> = Path::new(shared_bin).read_dir()?.flatten()
+        .map(|entry| entry.path())
+        .filter(|path| is_exe(path))
+        .collect();
+    bins.sort();
+    for bin in bins {
+        let name = bin.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let bin_path = Path::new(bin_dir).join(&name);
+        if exclude.contains(&name.as_str()) || bin_path.exists() || bin_path.is_symlink() {
+            continue
+        }
+        std::os::unix::fs::symlink(&sharun_link, &bin_path)?;
+        println!("{} -> {}", bin_path.display(), sharun_link.display())
+    }
+    Ok(())
+}
+
+fn normalize_rpath(path: &Path, lib_dirs: &[String], is_write: bool) -> Result<bool> {
+    let elf_map = get_elf(&path.to_string_lossy())?;
+    let (_, current_runpath) = get_runpath_slot(&elf_map)?;
+    let origin_dir = path.parent().unwrap_or(Path::new("/"));
+    let mut runpath_dirs: Vec<String> = Vec::new();
+    for lib_name in get_elf_needed_libs(&elf_map)? {
+        if let Some(lib_dir) = find_lib(lib_dirs, &lib_name).as_deref().and_then(|lib_path| lib_path.parent()) {
+            let relative_path = get_relative_path(origin_dir, lib_dir);
+            let runpath_dir = if relative_path.as_os_str().is_empty() {
+                "$ORIGIN".into()
+            } else {
+                format!("$ORIGIN/{}", relative_path.display())
+            };
+            if !runpath_dirs.contains(&runpath_dir) {
+                runpath_dirs.push(runpath_dir)
+            }
+        }
+    }
+    let new_runpath = runpath_dirs.join(":");
+    if new_runpath == current_runpath {
+        return Ok(false)
+    }
+    println!("{}: {current_runpath} => {new_runpath} ({})", path.display(),
+        if new_runpath.len() <= current_runpath.len() { "fits" } else { "too long" });
+    if is_write {
+        let mut elf_bytes = elf_map.to_vec();
+        drop(elf_map);
+        set_runpath(&mut elf_bytes, &new_runpath)?;
+        write_file(&path.to_string_lossy(), &elf_bytes)?;
+    }
+    Ok(true)
+}
+
+fn normalize_rpaths(sharun_dir: &str, shared_dir: &str, is_write: bool) -> bool {
+    let shared_lib = format!("{shared_dir}/lib");
+    let shared_lib32 = format!("{shared_dir}/lib32");
+    let lib_dirs = get_lib_dirs(sharun_dir, &shared_lib);
+    let lib32_dirs = get_lib_dirs(sharun_dir, &shared_lib32);
+    let mut is_ok = true;
+    let mut skipped_count = 0;
+    for dir in ["bin", "lib", "lib32"] {
+        let dir_path = Path::new(shared_dir).join(dir);
+        let walker = WalkDir::new(&dir_path).max_depth(if dir == "bin" { 1 } else { usize::MAX });
+        for entry in walker.into_iter().filter_map(|entry| log_scan_err(&dir_path, entry)) {
+            let path = entry.path();
+            if !entry.file_type().is_file() {
+                continue
+            }
+            let Some(elf_class) = get_elf_class(path) else { continue };
+            match normalize_rpath(path, if elf_class == 32 { &lib32_dirs } else { &lib_dirs }, is_write) {
+                Ok(_) => {}
+                Err(err) if err.kind() == NotFound => skipped_count += 1,
+                Err(err) => {
+                    eprintln!("Failed to normalize RUNPATH: {}: {err}", path.display());
+                    is_ok = false
+                }
+            }
+        }
+    }
+    if skipped_count > 0 {
+        println!("Skipped {skipped_count} ELF files without RUNPATH")
+    }
+    is_ok
+}
+
+fn collect_deps(deps: &[DepNode], paths: &mut Vec<PathBuf>) {
+    for dep in deps.iter().filter(|dep| !dep.is_seen) {
+        if let Some(lib_path) = &dep.path {
+            paths.push(lib_path.clone());
+            collect_deps(&dep.deps, paths)
+        }
+    }
+}
+
+fn get_glibc_versions(path: &Path, is_needed: bool) -> Result<Vec<String>> {
+    let elf_bytes = get_elf(&path.to_string_lossy())?;
+    let elf = Elf::parse(&elf_bytes)
+        .map_err(|err| Error::new(InvalidData, err))?;
+    let mut names = Vec::new();
+    if is_needed {
+        if let Some(verneed) = &elf.verneed {
+            for need_file in verneed.iter() {
+                for need_ver in need_file.iter() {
+                    names.push(elf.dynstrtab.get_at(need_ver.vna_name))
+                }
+            }
+        }
+    } else if let Some(verdef) = &elf.verdef {
+        for def in verdef.iter() {
+            for def_aux in def.iter() {
+                names.push(elf.dynstrtab.get_at(def_aux.vda_name))
+            }
+        }
+    }
+    Ok(names.into_iter().flatten()
+        .filter(|name| name.starts_with("GLIBC_") && *name != "GLIBC_PRIVATE")
+        .map(String::from)
+        .collect())
+}
+
+fn parse_glibc_version(version: &str) -> Vec<u32> {
+    version.trim_start_matches("GLIBC_").split('.')
+        .map(|num| num.parse().unwrap_or_default())
+        .collect()
+}
+
+fn max_glibc_version(versions: &[String]) -> Option<&String> {
+    versions.iter().max_by_key(|version| parse_glibc_version(version))
+}
+
+fn check_abi(bin: &str, library_path: &str, lib_dirs: &[String]) -> bool {
+    let bin_path = Path::new(bin);
+    let mut deps = Vec::new();
+    collect_deps(&get_dep_tree(bin_path, lib_dirs).0, &mut deps);
+    let mut required = Vec::new();
+    for path in std::iter::once(bin_path).chain(deps.iter().map(|dep| dep.as_path())) {
+        if path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("libc.so")) {
+            continue
+        }
+        for version in get_glibc_versions(path, true).unwrap_or_default() {
+            if !required.contains(&version) {
+                required.push(version)
+            }
+        }
+    }
+    let Some(max_required) = max_glibc_version(&required) else {
+        println!("Required: no GLIBC symbol versions");
+        return true
+    };
+    println!("Required: {max_required}");
+    let Some(libc) = find_lib(lib_dirs, "libc.so.6") else {
+        println!("Bundled glibc: not found");
+        return false
+    };
+    let provided = get_glibc_versions(&libc, false).unwrap_or_default();
+    println!("Bundled glibc: {} ({})", libc.display(),
+        max_glibc_version(&provided).map_or("no GLIBC versions", |version| version));
+    if let Ok(interpreter) = get_bin_interpreter(bin, library_path) {
+        let interp_versions = get_glibc_versions(&interpreter, false).unwrap_or_default();
+        println!("Interpreter: {} ({})", interpreter.display(),
+            max_glibc_version(&interp_versions).map_or("no GLIBC versions", |version| version));
+    }
+    let missing: Vec<&String> = required.iter()
+        .filter(|version| !provided.contains(version))
+        .collect();
+    if missing.is_empty() {
+        println!("OK");
+        true
+    } else {
+        for version in missing {
+            println!("Missing: {version}")
+        }
+        false
+    }
+}
+
+fn count_libs(lib_dirs: &[String]) {
+    let mut libs: std::collections::BTreeMap<String, Vec<&String>> = std::collections::BTreeMap::new();
+    for lib_dir in lib_dirs {
+        if let Ok(dir) = Path::new(lib_dir).read_dir() {
+            for entry in dir.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if is_file(&entry.path()) && (name.ends_with(".so") || name.contains(".so.")) {
+                    libs.entry(name).or_default().push(lib_dir)
+                }
+            }
+        }
+    }
+    println!("Library dirs: {}", lib_dirs.len());
+    println!("Libraries: {}", libs.len());
+    for (lib_name, dirs) in libs.iter().filter(|(_, dirs)| dirs.len() > 1) {
+        println!("Duplicate: {lib_name}: {}",
+            dirs.iter().map(|dir| dir.as_str()).collect::<Vec<&str>>().join(", "))
+    }
+}
+
+fn gen_bisect_dir(bisect_dir: &Path, bisect_libs: &[String], library_path: &str, sharun_dir: &str) -> Result<()> {
+    if bisect_dir.exists() {
+        std::fs::remove_dir_all(bisect_dir)?
+    }
+    std::fs::create_dir_all(bisect_dir)?;
+    let sys_lib_dirs: Vec<String> = library_path.split(':')
+        .filter(|lib_dir| !lib_dir.is_empty() && !lib_dir.starts_with(sharun_dir))
+        .map(String::from)
+        .collect();
+    for lib_name in bisect_libs {
+        match find_lib(&sys_lib_dirs, lib_name) {
+            Some(sys_lib) => {
+                eprintln!("Bisect: {lib_name} => {}", sys_lib.display());
+                std::os::unix::fs::symlink(sys_lib, bisect_dir.join(lib_name))?
+            }
+            None => eprintln!("WARNING: Bisect: {lib_name} not found in system, keeping bundled")
+        }
+    }
+    Ok(())
+}
+
+fn get_gpu_vendors() -> Vec<String> {
+    let mut gpu_vendors: Vec<String> = Vec::new();
+    if let Ok(dir) = Path::new("/sys/class/drm").read_dir() {
+        for entry in dir.flatten() {
+            let device = entry.path().join("device");
+            let vendor = match read_to_string(device.join("vendor")).unwrap_or_default().trim() {
+                "0x10de" => "nvidia",
+                "0x1002" => "amd",
+                "0x8086" => "intel",
+                _ => ""
+            };
+            let driver = device.join("driver").canonicalize().unwrap_or_default()
+                .file_name().unwrap_or_default().to_string_lossy().to_string();
+            for name in [vendor.into(), driver] {
+                if !name.is_empty() && !gpu_vendors.contains(&name) {
+                    gpu_vendors.push(name)
+                }
+            }
+        }
+    }
+    if Path::new("/sys/module/nvidia/version").exists() && !gpu_vendors.contains(&"nvidia".into()) {
+        gpu_vendors.push("nvidia".into())
+    }
+    gpu_vendors
+}
+
+fn gen_qt_conf(sharun_dir: &str, library_path: &str, qt_conf: &str) -> Result<()> {
+    let qt_dir = Path::new(library_path).read_dir().ok().and_then(|dir| {
+        dir.flatten()
+            .filter(|entry| entry.path().is_dir() && entry.file_name().to_string_lossy().starts_with("qt"))
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .max()
+    }).ok_or_else(|| Error::other(format!("Failed to find Qt directory in: {library_path}")))?;
+    let qt_path = Path::new(library_path).join(&qt_dir);
+    let share_qt_path = Path::new(sharun_dir).join("share").join(&qt_dir);
+    let lib_name = basename(library_path);
+    let mut data = format!("[Paths]\nPrefix = ../shared/{lib_name}/{qt_dir}\n");
+    if qt_path.join("plugins").is_dir() {
+        data += "Plugins = plugins\n"
+    }
+    if qt_path.join("qml").is_dir() {
+        data += "Qml2Imports = qml\n"
+    }
+    if qt_path.join("translations").is_dir() {
+        data += "Translations = translations\n"
+    } else if share_qt_path.join("translations").is_dir() {
+        data += &format!("Translations = ../../../share/{qt_dir}/translations\n")
+    }
+    if share_qt_path.is_dir() {
+        data += &format!("Data = ../../../share/{qt_dir}\n")
+    }
+    write(qt_conf, data)
+        .map_err(|err| Error::other(format!("Failed to write qt.conf: {qt_conf}: {err}")))?;
+    eprintln!("Write qt.conf: {qt_conf}");
+    Ok(())
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn export_manifest(sharun_dir: &str, manifest_file: &str) -> Result<()> {
+    let manifest_path = Path::new(manifest_file).canonicalize().ok();
+    let mut paths: Vec<PathBuf> = WalkDir::new(sharun_dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        // lib.path is rewritten by sharun itself
+        .filter(|entry| entry.file_type().is_file() &&
+            !["lib.path", "lib.path.stamp"].contains(&entry.file_name().to_string_lossy().as_ref()))
+        .map(|entry| entry.into_path())
+        .filter(|path| path.canonicalize().ok() != manifest_path)
+        .collect();
+    paths.sort();
+    let mut manifest = String::new();
+    for path in paths {
+        let hash = sha256_file(&path)
+            .map_err(|err| Error::other(format!("Failed to hash file: {}: {err}", path.display())))?;
+        let rel_path = path.strip_prefix(sharun_dir).unwrap_or(&path);
+        manifest += &format!("{hash}  {}\n", rel_path.display())
+    }
+    write(manifest_file, manifest)
+        .map_err(|err| Error::other(format!("Failed to write manifest: {manifest_file}: {err}")))?;
+    eprintln!("Write manifest: {manifest_file}");
+    Ok(())
+}
+
+fn verify_manifest(sharun_dir: &str, manifest_file: &str, library_path: &str) -> Result<bool> {
+    // only the libraries of the launched ELF class are checked, the rest of the bundle may change
+    let mut hashes = std::collections::HashMap::new();
+    for string in read_to_string(manifest_file)?.lines() {
+        let Some((hash, rel_path)) = string.split_once("  ") else { continue };
+        let path = Path::new(sharun_dir).join(rel_path);
+        if path.starts_with(library_path) && is_lib_file(&basename(rel_path)) {
+            hashes.insert(path, hash.to_string());
+        }
+    }
+    if hashes.is_empty() {
+        eprintln!("No libraries from {library_path} in the manifest");
+        return Ok(false)
+    }
+    let mut is_valid = true;
+    for entry in WalkDir::new(library_path).into_iter().flatten() {
+        if !entry.file_type().is_file() || !is_lib_file(&entry.file_name().to_string_lossy()) { continue }
+        let path = entry.path();
+        let Some(hash) = hashes.remove(path) else {
+            eprintln!("Not in manifest: {}", path.display());
+            is_valid = false;
+            continue
+        };
+        match sha256_file(path) {
+            Ok(file_hash) if file_hash == hash => {}
+            Ok(_) => {
+                eprintln!("Mismatch: {}", path.display());
+                is_valid = false
+            }
+            Err(err) => {
+                eprintln!("Failed to hash file: {}: {err}", path.display());
+                is_valid = false
+            }
+        }
+    }
+    for path in hashes.keys() {
+        eprintln!("Missing: {}", path.display());
+        is_valid = false
+    }
+    Ok(is_valid)
+}
+
+fn start_dbus_session(sharun_dir: &str, dbus_daemon: &Path) -> Result<(std::process::Child, String)> {
+    let mut command = Command::new(dbus_daemon);
+    command.args(["--session", "--nofork", "--nopidfile", "--print-address=1"]);
+    let session_conf = Path::new(sharun_dir).join("share/dbus-1/session.conf");
+    if session_conf.exists() {
+        command.arg(format!("--config-file={}", session_conf.display()));
+    }
+    // the bus must not outlive sharun even if it is killed with SIGKILL
+    unsafe { command.pre_exec(|| set_pdeathsig(Signal::SIGTERM).map_err(Error::from)) };
+    let mut child = command.stdout(std::process::Stdio::piped()).spawn()?;
+    let mut address = String::new();
+    if let Some(stdout) = child.stdout.take() {
+        BufReader::new(stdout).read_line(&mut address)?;
+    }
+    let address = address.trim().to_string();
+    if address.is_empty() {
+        child.kill().ok();
+        child.wait().ok();
+        return Err(Error::new(InvalidData, "dbus-daemon did not print the bus address"))
+    }
+    Ok((child, address))
+}
+
+static DBUS_CHILD_PID: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(0);
+
+extern "C" fn forward_signal(signal: std::ffi::c_int) {
+    let pid = DBUS_CHILD_PID.load(std::sync::atomic::Ordering::SeqCst);
+    if let (true, Ok(signal)) = (pid > 0, Signal::try_from(signal)) {
+        kill(Pid::from_raw(pid), signal).ok();
+    }
+}
+
+fn unshare_ns(sharun_dir: &str, namespaces: &str) -> Result<()> {
+    let mut flags = CloneFlags::empty();
+    for namespace in namespaces.split(',') {
+        match namespace.trim() {
+            "user" => flags |= CloneFlags::CLONE_NEWUSER,
+            "mount" => flags |= CloneFlags::CLONE_NEWNS,
+            namespace => return Err(Error::new(InvalidInput,
+                format!("Unsupported namespace: '{namespace}'"))
+            )
+        }
+    }
+    let (uid, gid) = (getuid(), getgid());
+    unshare(flags)?;
+    if flags.contains(CloneFlags::CLONE_NEWUSER) {
+        write("/proc/self/setgroups", "deny")?;
+        write("/proc/self/uid_map", format!("{uid} {uid} 1"))?;
+        write("/proc/self/gid_map", format!("{gid} {gid} 1"))?;
+    }
+    if flags.contains(CloneFlags::CLONE_NEWNS) {
+        mount(None::<&str>, "/", None::<&str>, MsFlags::MS_REC | MsFlags::MS_PRIVATE, None::<&str>)?;
+        mount(Some(sharun_dir), sharun_dir, None::<&str>, MsFlags::MS_BIND | MsFlags::MS_REC, None::<&str>)?;
+        // the locked flags of the source mount must be kept, otherwise the remount fails
+        // with EPERM in a user namespace (e.g. nosuid,nodev of FUSE AppImage mounts)
+        let mut remount_flags = MsFlags::MS_REMOUNT | MsFlags::MS_BIND | MsFlags::MS_RDONLY;
+        let mount_flags = statvfs(sharun_dir)?.flags();
+        for (fs_flag, ms_flag) in [
+            (FsFlags::ST_NOSUID, MsFlags::MS_NOSUID),
+            (FsFlags::ST_NODEV, MsFlags::MS_NODEV),
+            (FsFlags::ST_NOEXEC, MsFlags::MS_NOEXEC),
+            (FsFlags::ST_NOATIME, MsFlags::MS_NOATIME),
+            (FsFlags::ST_NODIRATIME, MsFlags::MS_NODIRATIME),
+            (FsFlags::ST_RELATIME, MsFlags::MS_RELATIME)
+        ] {
+            if mount_flags.contains(fs_flag) {
+                remount_flags |= ms_flag
+            }
+        }
+        mount(None::<&str>, sharun_dir, None::<&str>, remount_flags, None::<&str>)?;
+    }
+    Ok(())
+}
+
+fn exec_self_test(sharun: &Path, shared_bin: &str) -> bool {
+    let mut is_ok = true;
+    for (name, expected_code) in [("true", 0), ("false", 1)] {
+        let bin = format!("{shared_bin}/{name}");
+        // the host binary would not go through the bundled interpreter, so it proves nothing
+        if !Path::new(&bin).exists() {
+            println!("{name}: skipped: not bundled: {bin}");
+            continue
+        }
+        let is_elf32_bin = check_elf32(&bin).unwrap_or(false);
+        let exec_mode = if is_elf32_bin { "execve" } else { "userland-execve" };
+        match Command::new(sharun).arg(name).status() {
+            Ok(status) if status.code() == Some(expected_code) => {
+                println!("{name} ({exec_mode}): OK")
+            }
+            Ok(status) => {
+                println!("{name} ({exec_mode}): FAILED: {status}");
+                is_ok = false
+            }
+            Err(err) => {
+                println!("{name} ({exec_mode}): FAILED: {err}");
+                is_ok = false
+            }
+        }
+    }
+    is_ok
+}
+
+fn find_desktop_file(sharun_dir: &str) -> Option<PathBuf> {
+    let mut desktop_file = None;
+    if let Ok(dir) = Path::new(sharun_dir).read_dir() {
+        for entry in dir.flatten() {
+            let path = entry.path();
+            if is_file(&path) && entry.file_name().to_string_lossy().ends_with(".desktop") {
+                desktop_file = Some(path)
+            }
+        }
+    }
+    desktop_file
+}
+
+fn read_desktop_key(desktop_file: &Path, group: &str, key: &str) -> Result<String> {
+    let data = read_to_string(desktop_file)
+        .map_err(|err| Error::other(format!("Failed to read desktop file: {}: {err}", desktop_file.display())))?;
+    let mut is_group = false;
+    for string in data.split("\n") {
+        let string = string.trim();
+        if string.starts_with('[') {
+            is_group = string == format!("[{group}]")
+        } else if is_group {
+            if let Some(value) = string.strip_prefix(&format!("{key}=")) {
+                return Ok(value.trim().into())
+            }
+        }
+    }
+    Ok("".into())
+}
+
+fn get_appname(name: &str) -> String {
+    basename(name).replace("'", "").replace("\"", "")
+}
+
+fn strip_field_codes(exec: &str) -> String {
+    exec.split_whitespace()
+        .filter(|arg| !(arg.len() == 2 && arg.starts_with('%') && arg != &"%%"))
+        .map(|arg| arg.replace("%%", "%"))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn dump_desktop(sharun_dir: &str) -> Result<()> {
+    let appname_file = format!("{sharun_dir}/.app");
+    if Path::new(&appname_file).exists() {
+        let data = read_to_string(&appname_file).unwrap_or_default();
+        println!("File: {appname_file}");
+        println!("App: {}", get_appname(data.trim().split("\n").next().unwrap_or_default()));
+    } else if let Some(desktop_file) = find_desktop_file(sharun_dir) {
+        let exec = read_desktop_key(&desktop_file, "Desktop Entry", "Exec")?;
+        let command = strip_field_codes(&exec);
+        println!("File: {}", desktop_file.display());
+        println!("Exec: {exec}");
+        println!("Command: {command}");
+        println!("App: {}", get_appname(command.split_whitespace().next().unwrap_or_default()));
+        let path = read_desktop_key(&desktop_file, "Desktop Entry", "Path")?;
+        if !path.is_empty() {
+            println!("Path: {path}")
+        }
+    } else {
+        println!("No .app or .desktop file found in: {sharun_dir}")
+    }
+    Ok(())
+}
+
+fn print_usage() {
+    println!("[ {} ]
+
+[ Usage ]: {SHARUN_NAME} [OPTIONS] [EXEC ARGS]...",
+    env!("CARGO_PKG_DESCRIPTION"));
+    #[cfg(feature = "lib4bin")]
+    println!("     Use lib4bin for create 'bin' and 'shared' dirs");
+    println!("
+[ Arguments ]:
+    [EXEC ARGS]...              Command line arguments for execution
+
+[ Options ]:");
+    #[cfg(feature = "lib4bin")]
+    println!("     l,  lib4bin [ARGS]         Launch the built-in lib4bin");
+    println!("    -f,  --find 'PATTERN'       Find files in sharun directory by name glob pattern
+    -l,  --list                 Print the executables from the bin dir as JSON
+    -g,  --gen-lib-path [DIR]   Generate a lib.path file (for the specified library dir)
+         --migrate-lib-path     Drop missing dirs from lib.path files and normalize them
+         --gen-bins [--exclude NAME,...]
+                                Create symlinks to sharun in bin for all executables from shared/bin
+         --gen-qt-conf          Generate a bin/qt.conf file for bundled Qt
+         --exec-self-test       Check the exec path by launching the bundled 'true' and 'false'
+         --count-libs           Print the number of library dirs and libraries and duplicates
+         --dump-desktop         Print the .app or .desktop file resolution for AppRun
+         --which [NAME]         Print the full path of the bundled executable
+         --check [EXEC]         Check that all libraries required by the executable are bundled
+         --ldd [EXEC]           Print the bundled path each library of the executable resolves to
+         --trace-deps [EXEC]    Print the tree of bundled libraries required by the executable
+         --print-interpreter [EXEC]
+                                Print the path of the interpreter selected for the executable
+         --print-shared-bin [EXEC]
+                                Print the resolved path, ELF class and PyInstaller status
+                                of the executable
+         --normalize-rpaths [--write]
+                                Show or set (with --write) $ORIGIN-relative RUNPATH of all
+                                bundled ELF files to the dirs of their bundled libraries
+         --json-deps [EXEC]     Print the tree of bundled libraries required by the executable as JSON
+         --check-abi [EXEC]     Check that the bundled glibc provides the GLIBC versions required
+                                by the executable and its bundled libraries
+         --set-interp [EXEC] [--write]
+                                Show or patch (with --write) the ELF INTERP and RUNPATH
+                                of the executable to use the bundled interpreter and libraries
+                                (INTERP is absolute, so the bundle must not be moved after that)
+         --clean-rpath [EXEC] [--write]
+                                Show or remove (with --write) the absolute RUNPATH entries
+                                of the executable pointing outside the sharun directory
+         --export-manifest [FILE]
+                                Write sha256 manifest of sharun directory files
+                                (default: $SHARUN_DIR/.manifest, used by --verify)
+         --stdin-args [EXEC]    Launch the executable once for each line of args from stdin
+         --print-loader-cmd [EXEC] [ARGS]
+                                Print the shell command to launch the executable without sharun
+         --dry-run [EXEC] [ARGS]
+                                Print the working dir, env and command instead of launching
+         --verify [EXEC] [ARGS] Launch the executable only if the bundled libraries match
+                                the $SHARUN_DIR/.manifest from --export-manifest
+         --emit-plan-json [EXEC] [ARGS]
+                                Print the resolved launch plan as JSON (testing feature only)
+         --print-env-for KEY [EXEC]
+                                Print the value of env variable computed for the executable
+         --print-env, --env [EXEC]
+                                Print all env variables computed for the executable
+         --trace-env-sources [EXEC]
+                                Print all env variables with the sources that set them
+                                (inherited, dotenv, lib-detection, share-scan, etc-scan, cli, sharun)
+         --compare-bundles DIR [EXEC]
+                                Print the launch command and env differences between
+                                this and the other sharun directory
+         --bisect-libs 'LIBS' [EXEC]
+                                Use system libraries instead of the bundled ones (comma-separated)
+         --prepend-path DIR [EXEC]
+                                Add the directory to the beginning of PATH
+         --append-path DIR [EXEC]
+                                Add the directory to the end of PATH
+         --strip-args COUNT [EXEC]
+                                Drop the first COUNT arguments passed to the executable
+    -v,  --version              Print version
+         --version-json         Print version and build info as JSON
+    -h,  --help                 Print help
+
+[ Environments ]:
+    SHARUN_WORKING_DIR=/path       Specifies the path to the working directory
+    SHARUN_ALLOW_SYS_VKICD=1       Enables breaking system vulkan/icd.d for vulkan loader
+    SHARUN_ALLOW_LD_PRELOAD=1      Enables breaking LD_PRELOAD env variable
+    SHARUN_KEEP_LD_ENV=1           Disables removing of other LD_* env variables before launch
+    SHARUN_ALLOW_QT_PLUGIN_PATH=1  Enables breaking QT_PLUGIN_PATH env variable
+    SHARUN_NO_NVIDIA_EGL_PRIME=1   Disables NVIDIA EGL prime logic
+    SHARUN_QT_THEME=name           Specifies the Qt platform theme if QT_QPA_PLATFORMTHEME is not set
+    SHARUN_ENV_WHITELIST=HOME:DISPLAY
+                                   Passes only the specified host env variables to the executable
+                                   (plus the variables set by sharun and .env file)
+    SHARUN_FREETYPE_PROPERTIES     Specifies FREETYPE_PROPERTIES if it is not set
+    SHARUN_NO_A11Y=1               Disables the at-spi accessibility bridge
+    SHARUN_ISOLATE_SETTINGS=1      Uses the memory GSettings backend instead of host dconf
+                                   (or 'keyfile' for the keyfile GSettings backend)
+    SHARUN_PROOT=1                 Launch the interpreter under proot (bundled bin/proot or from PATH)
+                                   instead of userland exec (or path to the proot executable)
+    SHARUN_BACKGROUND=1            Launch the executable in the background and exit immediately
+    SHARUN_DBUS=isolated           Launch the executable with a private D-Bus session bus
+                                   (bundled bin/dbus-daemon, stopped after the executable exits)
+    SHARUN_VERBOSE=1               Print sharun dir, interpreter, library path, env changes
+                                   and interpreter args to stderr before launch
+    SHARUN_PRINTENV=1              Print environment variables to stderr
+    SHARUN_LOG=1                   Print errors encountered while scanning sharun directory
+                                   and the warnings that are only useful for debugging
+    SHARUN_STRICT=1                Abort the launch on errors that are otherwise only warnings
+    SHARUN_UNSHARE=user,mount      Unshare user and/or mount namespaces before launch
+                                   (mount namespace makes sharun directory read-only)
+    SHARUN_LDNAME=ld.so            Specifies the name of the interpreter
+                                   (default: the name from PT_INTERP of the executable)
+    SHARUN_FORCE_LIB32=1           Forces the use of shared/lib32 (skips ELF class detection)
+    SHARUN_FORCE_LIB64=1           Forces the use of shared/lib (skips ELF class detection)
+    SHARUN_NO_LIB_PATH=1           Ignore lib.path and find the library directories on each launch
+                                   (nothing is written, for read-only sharun directory)
+    SHARUN_LIBRARY_PATH            Library directories used as is instead of lib.path
+                                   (env setup only detects the bundled dirs from it)
+    SHARUN_EXTRA_LIBRARY_PATH      Extra library directories with highest priority
+                                   (used as is and prepended to lib.path or SHARUN_LIBRARY_PATH,
+                                   SHARUN_LIBRARY_PATH replaces lib.path, use this one to prepend)
+    SHARUN_FALLBACK_LIBRARY_PATH   Fallback library directories with lowest priority
+    SHARUN_CACHE_DIR=/path         Specifies the path to the cache directory for read-only sharun directory
+                                   (default: $XDG_CACHE_HOME/sharun/<hash>)
+    SHARUN_CONFIG=/path            Specifies the path to the config file (default: $SHARUN_DIR/sharun.toml, config feature only)
+    SHARUN_DIR                     Sharun directory");
+}
+
+// sets the env for the bundled lib dirs, lib_path_data is the lib.path content
+#[cfg(feature = "setenv")]
+pub fn setup_lib_env(sharun_dir: &str, library_path: &str, lib_path_data: &str) -> Result<()> {
+    let bin_dir = &format!("{sharun_dir}/bin");
+    let shared_dir = &format!("{sharun_dir}/shared");
+    let gio_launch_desktop = PathBuf::from(bin_dir).join("gio-launch-desktop");
+    if is_exe(&gio_launch_desktop) {
+        env::set_var("GIO_LAUNCH_DESKTOP", gio_launch_desktop)
+    }
+    let library_dir = Path::new(library_path);
+    let mut is_wayland = false;
+    if let Some(dir) = log_scan_err(library_dir, library_dir.read_dir()) {
+        for entry in dir.filter_map(|entry| log_scan_err(library_dir, entry)) {
+            let entry_path = entry.path();
+            let name = entry.file_name();
+            if let Some(name) = name.to_str() {
+                if entry_path.is_dir() {
+                    if name.starts_with("girepository-") {
+                        env::set_var("GI_TYPELIB_PATH", entry_path)
+                    }
+                } else if name.starts_with("libwayland-client.so") {
+                    is_wayland = true
+                }
+            }
+        }
+    }
+    // libwayland-client is also pulled in by CLI tools, so the warning is only for debugging
+    if is_wayland && get_env_var("SHARUN_LOG") == "1" &&
+        get_env_var("WAYLAND_DISPLAY").is_empty() && get_env_var("DISPLAY").is_empty() {
+        eprintln!("WARNING: Neither WAYLAND_DISPLAY nor DISPLAY is set, the app may fail to connect to the display!")
+    }
+
+    if !lib_path_data.is_empty() {
+        let dirs: std::collections::HashSet<&str> = lib_path_data.split("\n").map(|string|{
+            string.split("/").nth(1).unwrap_or("")
+        }).collect();
+        let xmodifiers = get_env_var("XMODIFIERS");
+        let host_im = xmodifiers.strip_prefix("@im=").unwrap_or_default();
+        let lib_rules = read_lib_rules(library_path)?;
+        for dir in dirs {
+            let dir_path = &format!("{library_path}/{dir}");
+            if dir.starts_with("python") {
+                if !is_writable(sharun_dir) {
+                    env::set_var("PYTHONDONTWRITEBYTECODE", "1")
+                }
+                for packages_dir in ["site-packages", "dist-packages", "lib-dynload"] {
+                    let packages_path = &format!("{dir_path}/{packages_dir}");
+                    if is_dir(packages_path) {
+                        append_to_env("PYTHONPATH", packages_path)
+                    }
+                }
+            }
+            if dir == "dri" {
+                env::set_var("LIBGL_DRIVERS_PATH", dir_path);
+                if get_env_var("SHARUN_NO_NVIDIA_EGL_PRIME") != "1" &&
+                    Path::new("/sys/module/nvidia/version").exists() {
+                        add_to_env("LIBVA_DRIVERS_PATH", "/run/opengl-driver/lib/dri");
+                        add_to_env("LIBVA_DRIVERS_PATH", "/usr/lib/dri");
+                        add_to_env("LIBVA_DRIVERS_PATH", "/usr/lib64/dri");
+                        #[cfg(target_arch = "x86_64")]
+                        add_to_env("LIBVA_DRIVERS_PATH", "/usr/lib/x86_64-linux-gnu/dri");
+                        #[cfg(target_arch = "aarch64")]
+                        add_to_env("LIBVA_DRIVERS_PATH", "/usr/lib/aarch64-linux-gnu/dri");
+                }
+                add_to_env("LIBVA_DRIVERS_PATH", dir_path)
+            }
+            if dir == "gbm" {
+                add_to_env("GBM_BACKENDS_PATH", "/run/opengl-driver/lib/gbm");
+                add_to_env("GBM_BACKENDS_PATH", "/usr/lib/gbm");
+                add_to_env("GBM_BACKENDS_PATH", "/usr/lib64/gbm");
+                #[cfg(target_arch = "x86_64")]
+                add_to_env("GBM_BACKENDS_PATH", "/usr/lib/x86_64-linux-gnu/gbm");
+                #[cfg(target_arch = "aarch64")]
+                add_to_env("GBM_BACKENDS_PATH", "/usr/lib/aarch64-linux-gnu/gbm");
+                add_to_env("GBM_BACKENDS_PATH", dir_path)
+            }
+            if dir.starts_with("gtk-") {
+                add_to_env("GTK_PATH", dir_path);
+                env::set_var("GTK_EXE_PREFIX", sharun_dir);
+                env::set_var("GTK_DATA_PREFIX", sharun_dir);
+                for entry in WalkDir::new(dir_path).into_iter().flatten() {
+                    let path = entry.path();
+                    if is_file(path) && entry.file_name().to_string_lossy() == "immodules.cache" {
+                        env::set_var("GTK_IM_MODULE_FILE", path);
+                        break
+                    }
+                }
+                if get_env_var("NO_AT_BRIDGE") != "1" &&
+                    Path::new(&format!("{dir_path}/modules/libatk-bridge.so")).exists() {
+                    add_to_env("GTK_MODULES", "atk-bridge")
+                }
+                if !host_im.is_empty() && get_env_var("GTK_IM_MODULE").is_empty() &&
+                    is_file_in_dir(dir_path, |name| {
+                        (name.starts_with(&format!("im-{host_im}")) ||
+                        name.starts_with(&format!("libim-{host_im}"))) && name.ends_with(".so")
+                    }) {
+                    env::set_var("GTK_IM_MODULE", host_im)
+                }
+                // GTK4 loads immodules, printbackends and media from GTK_PATH,
+                // but its GL/Vulkan renderers leave the window blank without a GPU
+                if dir == "gtk-4.0" && get_env_var("GSK_RENDERER").is_empty() &&
+                    get_gpu_vendors().is_empty() {
+                    env::set_var("GSK_RENDERER", "cairo")
+                }
+            }
+            if dir == "folks" {
+                for entry in WalkDir::new(dir_path).into_iter().flatten() {
+                    let path = entry.path();
+                    if path.is_dir() && entry.file_name().to_string_lossy() == "backends" {
+                        env::set_var("FOLKS_BACKEND_PATH", path);
+                        break
+                    }
+                }
+            }
+            if dir.starts_with("qt") {
+                let qt_conf = &format!("{bin_dir}/qt.conf");
+                let plugins = &format!("{dir_path}/plugins");
+                if !Path::new(qt_conf).exists() {
+                    if Path::new(plugins).exists() {
+                        add_to_env("QT_PLUGIN_PATH", plugins)
+                    }
+                    let platforms = &format!("{plugins}/platforms");
+                    if is_dir(platforms) && get_env_var("QT_QPA_PLATFORM_PLUGIN_PATH").is_empty() {
+                        env::set_var("QT_QPA_PLATFORM_PLUGIN_PATH", platforms)
+                    }
+                    if get_env_var("QT_QPA_PLATFORM").is_empty() &&
+                        (!get_env_var("WAYLAND_DISPLAY").is_empty() || get_env_var("XDG_SESSION_TYPE") == "wayland") {
+                        let qpa_platforms: Vec<&str> = [("wayland", "libqwayland"), ("xcb", "libqxcb")].iter()
+                            .filter(|(_, plugin)| is_file_in_dir(platforms, |name| name.starts_with(plugin)))
+                            .map(|(platform, _)| *platform)
+                            .collect();
+                        if qpa_platforms.first() == Some(&"wayland") {
+                            env::set_var("QT_QPA_PLATFORM", qpa_platforms.join(";"))
+                        }
+                    }
+                    if let Some(qml) = [dir_path, library_path, shared_dir].iter()
+                        .map(|dir| format!("{dir}/qml"))
+                        .find(|qml| is_dir(qml)) {
+                        add_to_env("QML2_IMPORT_PATH", &qml);
+                        add_to_env("QML_IMPORT_PATH", &qml)
+                    }
+                }
+                let input_contexts = &format!("{plugins}/platforminputcontexts");
+                if !host_im.is_empty() && get_env_var("QT_IM_MODULE").is_empty() &&
+                    is_file_in_dir(input_contexts, |name| {
+                        name.starts_with(&format!("lib{host_im}")) && name.ends_with(".so")
+                    }) {
+                    env::set_var("QT_IM_MODULE", host_im)
+                }
+                if get_env_var("QT_QPA_PLATFORMTHEME").is_empty() {
+                    let qt_theme = get_env_var("SHARUN_QT_THEME");
+                    if !qt_theme.is_empty() {
+                        env::set_var("QT_QPA_PLATFORMTHEME", qt_theme)
+                    } else {
+                        let platform_themes = &format!("{plugins}/platformthemes");
+                        let desktop = get_env_var("XDG_CURRENT_DESKTOP").to_uppercase();
+                        let themes = if desktop.contains("KDE") {
+                            [("kde", "libKDEPlasmaPlatformTheme"), ("xdgdesktopportal", "libqxdgdesktopportal")]
+                        } else {
+                            [("gtk3", "libqgtk3"), ("xdgdesktopportal", "libqxdgdesktopportal")]
+                        };
+                        if let Some((theme, _)) = themes.iter().find(|(_, plugin)| {
+                            is_file_in_dir(platform_themes, |name| name.starts_with(plugin))
+                        }) {
+                            env::set_var("QT_QPA_PLATFORMTHEME", theme)
+                        }
+                    }
+                }
+            }
+            if dir.starts_with("ImageMagick-") {
+                env::set_var("MAGICK_HOME", sharun_dir);
+                for entry in WalkDir::new(dir_path).max_depth(2).into_iter().flatten() {
+                    let path = entry.path();
+                    let name = entry.file_name().to_string_lossy();
+                    if path.is_dir() {
+                        if name == "coders" {
+                            env::set_var("MAGICK_CODER_MODULE_PATH", path)
+                        } else if name == "filters" {
+                            env::set_var("MAGICK_CODER_FILTER_PATH", path)
+                        } else if name.starts_with("config-") {
+                            add_to_env("MAGICK_CONFIGURE_PATH", path)
+                        }
+                    }
+                }
+            }
+            if dir.starts_with("vips-modules-") {
+                if let Some(vips_home) = Path::new(library_path).parent() {
+                    env::set_var("VIPSHOME", vips_home)
+                }
+            }
+            if dir == "jvm" || dir.starts_with("jre") || dir.starts_with("jdk") || dir.starts_with("java") {
+                for entry in WalkDir::new(dir_path).into_iter().flatten() {
+                    let name = entry.file_name().to_string_lossy();
+                    if name == "libjvm.so" || name == "libjli.so" {
+                        // lib/server/libjvm.so, lib/libjli.so or lib/amd64/server/libjvm.so
+                        if let Some(java_home) = entry.path().ancestors().skip(1)
+                            .take_while(|path| path.starts_with(dir_path)).find(|path| {
+                            path.join("release").is_file() || is_exe(&path.join("bin/java"))
+                        }) {
+                            env::set_var("JAVA_HOME", java_home);
+                            break
+                        }
+                    }
+                }
+            }
+            if dir.starts_with("tcl") && Path::new(&format!("{dir_path}/msgs")).exists() {
+                add_to_env("TCL_LIBRARY", dir_path);
+                let tk = &format!("{library_path}/{}", dir.replace("tcl", "tk"));
+                if Path::new(&tk).exists() {
+                    add_to_env("TK_LIBRARY", tk)
+                }
+            }
+            if dir.starts_with("gdk-pixbuf-") {
+                let mut loaders_dir = None;
+                let mut loaders_cache = None;
+                for entry in WalkDir::new(dir_path).into_iter().flatten() {
+                    let path = entry.path();
+                    let name = entry.file_name().to_string_lossy();
+                    if name == "loaders" && path.is_dir() {
+                        env::set_var("GDK_PIXBUF_MODULEDIR", path);
+                        loaders_dir = Some(path.to_path_buf())
+                    }
+                    if name == "loaders.cache" && is_file(path) {
+                        env::set_var("GDK_PIXBUF_MODULE_FILE", path);
+                        loaders_cache = Some(path.to_path_buf())
+                    }
+                    if loaders_dir.is_some() && loaders_cache.is_some() {
+                        break
+                    }
+                }
+                if let (Some(loaders_dir), Some(loaders_cache)) = (loaders_dir, loaders_cache) {
+                    if !is_loaders_cache_valid(&loaders_cache, Path::new(dir_path)) {
+                        if let Some(cache_loaders_cache) = update_loaders_cache(sharun_dir, bin_dir,
+                            Path::new(dir_path), &loaders_dir) {
+                            env::set_var("GDK_PIXBUF_MODULE_FILE", cache_loaders_cache)
+                        }
+                    }
+                }
+            }
+            for rule in lib_rules.iter().filter(|rule| {
+                if rule.is_prefix { dir.starts_with(&rule.pattern) } else { dir == rule.pattern }
+            }) {
+                for rule_env in &rule.envs {
+                    let val = rule_env.val.replace("$dir", dir_path)
+                        .replace("$sharun_dir", sharun_dir)
+                        .replace("$library_path", library_path);
+                    if rule_env.is_if_exists && !Path::new(&val).exists() { continue }
+                    if rule_env.is_add { add_to_env(&rule_env.key, val) } else { env::set_var(&rule_env.key, val) }
+                }
+            }
+        }
+    }
+
+    env::remove_var("SHARUN_QT_THEME");
+
+    // glibc reads only the compiled locale dirs from LOCPATH, locale-archive is ignored there
+    let locale_dir = &format!("{library_path}/locale");
+    if get_env_var("LOCPATH").is_empty() && is_file_in_dir(locale_dir, |name| name == "LC_CTYPE") {
+        env::set_var("LOCPATH", locale_dir)
+    }
+    Ok(())
+}
+
+// sets the env for the data dirs from $SHARUN_DIR/share
+#[cfg(feature = "setenv")]
+pub fn setup_share_env(sharun_dir: &str) -> Result<()> {
+    let bin_dir = &format!("{sharun_dir}/bin");
+    let share_dir = PathBuf::from(format!("{sharun_dir}/share"));
+    if check_bundle_dir(&share_dir)? {
+        if let Some(dir) = log_scan_err(&share_dir, share_dir.read_dir()) {
+            add_to_env("XDG_DATA_DIRS", "/run/current-system/sw/share");
+            add_to_env("XDG_DATA_DIRS", "/run/opengl-driver/share");
+            add_to_env("XDG_DATA_DIRS", "/usr/share");
+            add_to_env("XDG_DATA_DIRS", "/usr/local/share");
+            add_to_env("XDG_DATA_DIRS", format!("{}/.local/share", get_env_var("HOME")));
+            add_to_env("XDG_DATA_DIRS", &share_dir);
+            let xdg_data_dirs = &get_env_var("XDG_DATA_DIRS");
+            for entry in dir.filter_map(|entry| log_scan_err(&share_dir, entry)) {
+                let entry_path = entry.path();
+                if entry_path.is_dir() {
+                    let name = entry.file_name();
+                    match name.to_str().unwrap_or_default() {
+                        "glvnd" => {
+                            if get_env_var("SHARUN_NO_NVIDIA_EGL_PRIME") != "1" &&
+                               Path::new("/sys/module/nvidia/version").exists() &&
+                               get_env_var("__EGL_VENDOR_LIBRARY_FILENAMES").is_empty() {
+                               let mut xdg_json_paths = Vec::new();
+                               for xdg_data_dir in xdg_data_dirs.split(":") {
+                                   let egl_vendor = Path::new(xdg_data_dir).join("glvnd/egl_vendor.d");
+                                   let mut paths = collect_json_files(&egl_vendor);
+                                   xdg_json_paths.append(&mut paths)
+                               }
+                               let nvidia_json = xdg_json_paths.iter()
+                                   .find(|p| p.file_name().unwrap_or_default().to_string_lossy().contains("nvidia"));
+                               if let Some(nvidia_path) = nvidia_json {
+                                   let mut all_paths = Vec::new();
+                                   all_paths.push(nvidia_path.clone());
+                                   for path in xdg_json_paths.iter() {
+                                       if !path.file_name().unwrap_or_default().to_string_lossy().contains("nvidia") {
+                                           all_paths.push(path.clone())
+                                       }
+                                   }
+                                   if !all_paths.is_empty() {
+                                       let paths_str = all_paths.iter()
+                                           .map(|p| p.to_string_lossy())
+                                           .collect::<Vec<_>>()
+                                           .join(":");
+                                       env::set_var("__EGL_VENDOR_LIBRARY_FILENAMES", &paths_str)
+                                   }
+                               }
+                           }
+                            add_to_xdg_data_env(xdg_data_dirs,
+                                "__EGL_VENDOR_LIBRARY_DIRS", "glvnd/egl_vendor.d")
+                        }
+                        "vulkan" => {
+                            let vk_dir = "vulkan/icd.d";
+                            let vk_env = "VK_DRIVER_FILES";
+                            if get_env_var("SHARUN_ALLOW_SYS_VKICD") == "1" {
+                                env::remove_var("SHARUN_ALLOW_SYS_VKICD");
+                                add_to_xdg_data_env(xdg_data_dirs, vk_env, vk_dir)
+                            } else {
+                                for xdg_data_dir in xdg_data_dirs.rsplit(":") {
+                                    let vk_icd_dir = Path::new(xdg_data_dir).join(vk_dir);
+                                    if vk_icd_dir.exists() {
+                                        if xdg_data_dir.starts_with(share_dir.to_str().unwrap_or_default()) {
+                                            add_to_env(vk_env, vk_icd_dir);
+                                        } else if let Ok(dir) = vk_icd_dir.read_dir() {
+                                            for entry in dir.flatten() {
+                                                let path = entry.path();
+                                                if is_file(&path) &&
+                                                    entry.file_name().to_string_lossy().contains("nvidia") {
+                                                    add_to_env(vk_env, path)
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        "alsa" => {
+                            let alsa_conf = entry_path.join("alsa.conf");
+                            if !Path::new("/usr/share/alsa/alsa.conf").exists() && alsa_conf.exists() {
+                                env::set_var("ALSA_CONFIG_PATH", alsa_conf)
+                            }
+                        }
+                        "pipewire" => {
+                            if !Path::new("/usr/share/pipewire").exists() && entry_path.join("client.conf").exists() &&
+                                get_env_var("PIPEWIRE_CONFIG_DIR").is_empty() {
+                                env::set_var("PIPEWIRE_CONFIG_DIR", entry_path)
+                            }
+                        }
+                        "drirc.d" => {
+                            let sys_drirc_dir = Path::new("/usr/share/drirc.d");
+                            if !sys_drirc_dir.exists() {
+                                env::set_var("DRIRC_CONFIGDIR", entry_path)
+                            }
+                        }
+                        "X11" => {
+                            let xkb = &entry_path.join("xkb");
+                            if !Path::new("/usr/share/X11/xkb").exists() && xkb.exists() {
+                                env::set_var("XKB_CONFIG_ROOT", xkb)
+                            }
+                            let xlocale = &entry_path.join("locale");
+                            if !Path::new("/usr/share/X11/locale").exists() && xlocale.exists() {
+                                env::set_var("XLOCALEDIR", xlocale)
+                            }
+                        }
+                        "libdrm" => {
+                            add_to_env("AMDGPU_ASIC_ID_TABLE_PATHS", entry_path);
+                            add_to_env("AMDGPU_ASIC_ID_TABLE_PATHS", "/usr/share/libdrm");
+                            add_to_env("AMDGPU_ASIC_ID_TABLE_PATHS", "/usr/local/share/libdrm")
+                        }
+                        "libthai" => {
+                            if entry_path.join("thbrk.tri").exists() {
+                                env::set_var("LIBTHAI_DICTDIR", entry_path)
+                            }
+                        }
+                        "glib-2.0" => {
+                            add_to_xdg_data_env(xdg_data_dirs,
+                                "GSETTINGS_SCHEMA_DIR", "glib-2.0/schemas");
+                            if let Some(schemas_dir) = compile_schemas(sharun_dir, bin_dir, &entry_path.join("schemas")) {
+                                add_to_env("GSETTINGS_SCHEMA_DIR", schemas_dir)
+                            }
+                        }
+                        "terminfo" => {
+                            env::set_var("TERMINFO", entry_path)
+                        }
+                        "locale" => {
+                            env::set_var("TEXTDOMAINDIR", entry_path)
+                        }
+                        "file" => {
+                            let magic_file = &entry_path.join("misc/magic.mgc");
+                            if magic_file.exists() {
+                                env::set_var("MAGIC", magic_file)
+                            }
+                        }
+                        "icons" => {
+                            if let Some(cache_share_dir) = update_icon_cache(sharun_dir, bin_dir, &entry_path) {
+                                add_to_env("XDG_DATA_DIRS", cache_share_dir)
+                            }
+                            let cursor_file = Path::new(sharun_dir).join(".cursor");
+                            let cursor_data = read_to_string(&cursor_file).unwrap_or_default();
+                            let mut cursor = cursor_data.split_whitespace();
+                            if let Some(theme) = cursor.next() {
+                                if entry_path.join(theme).join("cursors").is_dir() {
+                                    let home = get_env_var("HOME");
+                                    add_to_env("XCURSOR_PATH", "/usr/share/pixmaps");
+                                    add_to_env("XCURSOR_PATH", "/usr/share/icons");
+                                    add_to_env("XCURSOR_PATH", format!("{home}/.icons"));
+                                    add_to_env("XCURSOR_PATH", format!("{home}/.local/share/icons"));
+                                    add_to_env("XCURSOR_PATH", &entry_path);
+                                    if get_env_var("XCURSOR_THEME").is_empty() {
+                                        env::set_var("XCURSOR_THEME", theme)
+                                    }
+                                    if let Some(size) = cursor.next() {
+                                        if get_env_var("XCURSOR_SIZE").is_empty() {
+                                            env::set_var("XCURSOR_SIZE", size)
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        "man" | "info" => {
+                            let env_name = if name == "man" { "MANPATH" } else { "INFOPATH" };
+                            if get_env_var(env_name).is_empty() {
+                                // trailing ':' keeps the system default search path
+                                env::set_var(env_name, format!("{}:", entry_path.display()))
+                            } else {
+                                add_to_env(env_name, entry_path)
+                            }
+                        }
+                        "vim" => {
+                            if let Ok(dir) = entry_path.read_dir() {
+                                let vim_runtime = dir.flatten()
+                                    .map(|entry| entry.path())
+                                    .filter(|path| path.is_dir() &&
+                                        path.file_name().unwrap_or_default().to_string_lossy().starts_with("vim") &&
+                                        path.join("syntax").is_dir())
+                                    .max();
+                                if let Some(vim_runtime) = vim_runtime {
+                                    env::set_var("VIMRUNTIME", vim_runtime)
+                                }
+                            }
+                        }
+                        "ghostscript" => {
+                            if let Ok(dir) = entry_path.read_dir() {
+                                for gs_dir in dir.flatten().map(|entry| entry.path()) {
+                                    if gs_dir.join("Resource").is_dir() {
+                                        for gs_lib in ["Resource/Font", "lib", "Resource/Init"] {
+                                            let gs_lib = gs_dir.join(gs_lib);
+                                            if gs_lib.is_dir() {
+                                                add_to_env("GS_LIB", gs_lib)
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                            let gs_fonts = entry_path.join("fonts");
+                            if gs_fonts.is_dir() {
+                                add_to_env("GS_FONTPATH", gs_fonts)
+                            }
+                        }
+                        "xdg-desktop-portal" => {
+                            let portals = entry_path.join("portals");
+                            if portals.is_dir() {
+                                env::set_var("XDG_DESKTOP_PORTAL_DIR", portals)
+                            }
+                        }
+                        "tessdata" | "tesseract" | "tesseract-ocr" => {
+                            // tesseract >= 4 expects the tessdata dir itself
+                            for entry in WalkDir::new(&entry_path).into_iter().flatten() {
+                                let path = entry.path();
+                                if path.is_dir() && entry.file_name() == "tessdata" {
+                                    env::set_var("TESSDATA_PREFIX", path);
+                                    break
+                                }
+                            }
+                        }
+                        "icu" => {
+                            for entry in WalkDir::new(&entry_path).into_iter().flatten() {
+                                let path = entry.path();
+                                if is_file(path) && entry.file_name().to_string_lossy().ends_with(".dat") {
+                                    if let Some(icu_data) = path.parent() {
+                                        env::set_var("ICU_DATA", icu_data);
+                                        break
+                                    }
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// sets the env for the configs from $SHARUN_DIR/etc and the CA certificates
+#[cfg(feature = "setenv")]
+pub fn setup_etc_env(sharun_dir: &str) -> Result<()> {
+    let etc_dir = PathBuf::from(format!("{sharun_dir}/etc"));
+    if check_bundle_dir(&etc_dir)? {
+        if let Some(dir) = log_scan_err(&etc_dir, etc_dir.read_dir()) {
+            for entry in dir.filter_map(|entry| log_scan_err(&etc_dir, entry)) {
+                let entry_path = entry.path();
+                if entry_path.is_dir() {
+                    let name = entry.file_name();
+                    match name.to_str().unwrap_or_default() {
+                        "fonts" => {
+                            let fonts_conf = entry_path.join("fonts.conf");
+                            if !Path::new("/etc/fonts/fonts.conf").exists() && fonts_conf.exists() {
+                                env::set_var("FONTCONFIG_PATH", &entry_path);
+                                env::set_var("FONTCONFIG_FILE",
+                                    gen_fonts_conf(sharun_dir, &fonts_conf).unwrap_or(fonts_conf))
+                            }
+                        }
+                        "alsa" => {
+                            let alsa_conf = entry_path.join("alsa.conf");
+                            if !Path::new("/usr/share/alsa/alsa.conf").exists() && alsa_conf.exists() &&
+                                get_env_var("ALSA_CONFIG_PATH").is_empty() {
+                                env::set_var("ALSA_CONFIG_PATH", alsa_conf)
+                            }
+                        }
+                        "pipewire" => {
+                            if !Path::new("/usr/share/pipewire").exists() && entry_path.join("client.conf").exists() &&
+                                get_env_var("PIPEWIRE_CONFIG_DIR").is_empty() {
+                                env::set_var("PIPEWIRE_CONFIG_DIR", entry_path)
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    if !Path::new("/etc/ssl/certs/ca-certificates.crt").exists() {
+        let possible_certs = [
+            "/etc/pki/tls/cert.pem",
+            "/etc/pki/tls/cacert.pem",
+            "/etc/ssl/cert.pem",
+            "/var/lib/ca-certificates/ca-bundle.pem",
+        ];
+
+        let bundled_certs = [
+            format!("{sharun_dir}/etc/ssl/certs/ca-certificates.crt"),
+            format!("{sharun_dir}/etc/ssl/cert.pem"),
+            format!("{sharun_dir}/share/ca-certificates/ca-certificates.crt"),
+            format!("{sharun_dir}/share/ca-certificates/cert.pem"),
+        ];
+
+        if let Some(found_cert) = possible_certs.iter().find(|&&path| Path::new(path).exists()) {
+            for var_name in ["REQUESTS_CA_BUNDLE", "CURL_CA_BUNDLE", "SSL_CERT_FILE"].iter() {
+                if env::var_os(var_name).is_none() {
+                    env::set_var(var_name, found_cert);
+                }
+            }
+        } else if let Some(bundled_cert) = bundled_certs.iter().find(|path| is_file(Path::new(path))) {
+            for var_name in ["REQUESTS_CA_BUNDLE", "CURL_CA_BUNDLE", "SSL_CERT_FILE", "GIT_SSL_CAINFO"].iter() {
+                if env::var_os(var_name).is_none() {
+                    env::set_var(var_name, bundled_cert);
+                }
+            }
+            let bundled_certs_dir = format!("{sharun_dir}/etc/ssl/certs");
+            if is_dir(&bundled_certs_dir) && env::var_os("SSL_CERT_DIR").is_none() {
+                env::set_var("SSL_CERT_DIR", bundled_certs_dir)
+            }
+        } else {
+            eprintln!("WARNING: Cannot find CA Certificates in host!");
+        }
+    }
+    Ok(())
+}
+
+// the --library-path for the interpreter: lib.path (or SHARUN_LIBRARY_PATH), then the env and the system dirs
+pub fn resolve_library_path(sharun_dir: &str, library_path: &str, lib_path_data: &str, is_elf32_bin: bool,
+    sharun_library_path: &str, bisect_libs: &[String]) -> Result<String> {
+    let mut library_path = if lib_path_data.is_empty() {
+        library_path.to_string()
+    } else {
+        lib_path_data.trim()
+            .replace("\n", ":")
+            .replace("+", library_path)
+    };
+
+    let ld_library_path_env = &get_env_var("LD_LIBRARY_PATH");
+    if !ld_library_path_env.is_empty() {
+        library_path += &format!(":{ld_library_path_env}")
+    }
+
+    library_path += ":/usr/lib:/lib";
+    if is_elf32_bin {
+        library_path += ":/usr/lib32:/lib32";
+        #[cfg(target_arch = "x86_64")]
+        { library_path += ":/usr/lib/i386-linux-gnu" }
+    } else {
+        library_path += ":/usr/lib64:/lib64";
+        #[cfg(target_arch = "x86_64")]
+        { library_path += ":/usr/lib/x86_64-linux-gnu" }
+        #[cfg(target_arch = "aarch64")]
+        { library_path += ":/usr/lib/aarch64-linux-gnu" }
+        #[cfg(target_arch = "riscv64")]
+        { library_path += ":/usr/lib/riscv64-linux-gnu" }
+        #[cfg(target_arch = "loongarch64")]
+        { library_path += ":/usr/lib/loongarch64-linux-gnu" }
+    }
+    library_path += ":/run/opengl-driver/lib:/run/current-system/sw/lib";
+
+    let fallback_library_path = get_env_var("SHARUN_FALLBACK_LIBRARY_PATH");
+    if !fallback_library_path.is_empty() {
+        library_path = format!("{}:{}", library_path, fallback_library_path);
+        env::remove_var("SHARUN_FALLBACK_LIBRARY_PATH");
+    }
+
+    if !sharun_library_path.is_empty() {
+        library_path = sharun_library_path.into()
+    }
+
+    // SHARUN_LIBRARY_PATH already means "instead of lib.path", so prepending is a separate var
+    let extra_library_path = get_env_var("SHARUN_EXTRA_LIBRARY_PATH");
+    if !extra_library_path.is_empty() {
+        library_path = format!("{}:{}", extra_library_path, library_path);
+        env::remove_var("SHARUN_EXTRA_LIBRARY_PATH");
+    }
+
+    if !bisect_libs.is_empty() {
+        let bisect_dir = get_cache_dir(sharun_dir).join("bisect");
+        gen_bisect_dir(&bisect_dir, bisect_libs, &library_path, sharun_dir)
+            .map_err(|err| Error::other(format!("Failed to prepare bisect directory: {}: {err}", bisect_dir.display())))?;
+        library_path = format!("{}:{library_path}", bisect_dir.display())
+    }
+    Ok(library_path)
+}
+
+// the interpreter argv for the launch of bin, argv0 is what the executable sees as its path
+pub fn get_interpreter_args(sharun_dir: &str, interpreter: &Path, library_path: &str, bin: &str, argv0: &str,
+    exec_args: &[String], ld_preload: &str) -> Result<Vec<CString>> {
+    let mut interpreter_args: Vec<CString> = Vec::new();
+    interpreter_args.append(&mut vec![
+        CString::from_str(&interpreter.to_string_lossy()).unwrap_or_default(),
+        CString::new("--library-path").unwrap_or_default(),
+        CString::new(library_path).unwrap_or_default(),
+        CString::new("--argv0").unwrap_or_default(),
+        CString::new(argv0).unwrap_or_default()
+    ]);
+
+    let ldargs_path = PathBuf::from(format!("{sharun_dir}/.ldargs"));
+    if ldargs_path.exists() {
+        let data = read_to_string(&ldargs_path)
+            .map_err(|err| Error::other(format!("Failed to read .ldargs file: {}: {err}", ldargs_path.display())))?;
+        for string in data.trim().split("\n") {
+            let string = string.trim();
+            if string.starts_with('#') { continue }
+            for arg in string.split_whitespace() {
+                interpreter_args.push(CString::new(arg).unwrap_or_default())
+            }
+        }
+    }
+
+    let mut preload: Vec<String> = vec![];
+    let preload_path = PathBuf::from(format!("{sharun_dir}/.preload"));
+    if preload_path.exists() {
+        let data = read_to_string(&preload_path)
+            .map_err(|err| Error::other(format!("Failed to read .preload file: {}: {err}", preload_path.display())))?;
+        let gpu_vendors = if data.contains('[') { get_gpu_vendors() } else { vec![] };
+        for string in data.trim().split("\n") {
+            let mut string = string.trim();
+            if let Some((condition, lib)) = string.strip_prefix('[').and_then(|s| s.split_once(']')) {
+                if !gpu_vendors.contains(&condition.trim().to_lowercase()) {
+                    continue
+                }
+                string = lib.trim()
+            }
+            preload.append(&mut expand_glob(sharun_dir, string));
+        }
+    }
+    for lib in ld_preload.split([':', ' ']) {
+        if !lib.is_empty() && !preload.iter().any(|preload_lib| preload_lib == lib) {
+            preload.push(lib.into())
+        }
+    }
+    if !preload.is_empty() {
+        interpreter_args.append(&mut vec![
+            CString::new("--preload").unwrap_or_default(),
+            CString::new(preload.join(" ")).unwrap_or_default()
+        ])
+    }
+
+    interpreter_args.push(CString::new(bin).unwrap_or_default());
+    for arg in exec_args {
+        interpreter_args.push(CString::from_str(arg).unwrap_or_default())
+    }
+    Ok(interpreter_args)
+}
+
+pub fn exec_bundle(bundle_dir: &str, bin_name: &str, args: &[String]) -> Result<i32> {
+    let sharun = Path::new(bundle_dir).join(SHARUN_NAME);
+    env::set_var("SHARUN_DIR", bundle_dir);
+    let mut exec_args = vec![sharun.to_string_lossy().to_string(), bin_name.into()];
+    exec_args.extend_from_slice(args);
+    run(sharun, exec_args)
+}
+
+// returns the exit code of the commands handled by sharun itself, a launch replaces the process
+pub fn run(sharun: PathBuf, mut exec_args: Vec<String>) -> Result<i32> {
+    #[cfg(feature = "testing")]
+    let initial_env: std::collections::BTreeMap<String, String> = env::vars().collect();
+
+    let mut sharun_dir = realpath(&get_env_var("SHARUN_DIR")).unwrap_or_default();
+    if sharun_dir.is_empty() ||
+        !(is_dir(&sharun_dir) && {
+            let sharun_dir_path = Path::new(&sharun_dir);
+            let sharun_path = sharun_dir_path.join(SHARUN_NAME);
+            sharun_dir_path.join("shared").is_dir() && is_exe(&sharun_path) &&
+            is_same_rootdir(sharun_dir_path, &sharun, &sharun_path)
+        })
+    {
+        let sharun_parent = sharun.parent().ok_or_else(|| Error::other("Failed to get sharun parrent dir!"))?;
+        // the nearest dir with the bundle layout is the root, so that nested bundles use their own shared dir,
+        // only a few levels up and with the sharun or .app marker, a bare shared dir can be anything
+        let bundle_dir = sharun_parent.ancestors().take(4)
+            .find(|dir| dir.join("shared").is_dir() &&
+                (dir.join(".app").is_file() || is_exe(&dir.join(SHARUN_NAME))));
+        if bundle_dir.is_none() && !sharun_parent.join("shared").is_dir() && get_env_var("SHARUN_LOG") == "1" {
+            eprintln!("WARNING: No bundle dir with shared and {SHARUN_NAME} or .app found above: {}, using it as sharun dir",
+                sharun_parent.display())
+        }
+        sharun_dir = bundle_dir.unwrap_or(sharun_parent)
+            .to_str().unwrap_or_default().to_string();
+        env::set_var("SHARUN_DIR", &sharun_dir)
+    }
+
+    #[cfg(feature = "config")]
+    read_config(&sharun_dir)?;
+
+    let is_verbose = get_env_var("SHARUN_VERBOSE") == "1";
+    env::remove_var("SHARUN_VERBOSE");
+    let verbose_env: std::collections::BTreeMap<String, String> = if is_verbose {
+        eprintln!("sharun: sharun_dir: {sharun_dir}");
+        env::vars().collect()
+    } else {
+        std::collections::BTreeMap::new()
+    };
+
+    let bin_dir = &format!("{sharun_dir}/bin");
+    let shared_dir = &format!("{sharun_dir}/shared");
+    let shared_bin = &format!("{shared_dir}/bin");
+    let shared_lib = format!("{shared_dir}/lib");
+    let shared_lib32 = format!("{shared_dir}/lib32");
+
+    let arg0 = PathBuf::from(exec_args.remove(0));
+    let arg0_name = arg0.file_name().unwrap_or_default().to_str().unwrap_or_default();
+    let arg0_dir = match PathBuf::from(dirname(arg0.to_str().unwrap_or_default())).canonicalize() {
+        Ok(arg0_dir) => arg0_dir,
+        Err(_) => {
+            let which_arg0 = which(arg0_name).ok_or_else(|| Error::other("Failed to find ARG0 dir!"))?;
+            which_arg0.parent().ok_or_else(|| Error::other("Failed to get ARG0 parrent dir!"))?.to_path_buf()
+        }
+    };
+
+    let arg0_path = arg0_dir.join(arg0_name);
+    let arg0_full_path = arg0_path.canonicalize().unwrap_or_default();
+    let arg0_full_path_name = arg0_full_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let mut bin_name = if arg0_path.is_symlink() &&
+        arg0_full_path == Path::new(&sharun_dir).join(SHARUN_NAME) {
+        arg0_name.into()
+    } else if arg0_path.is_symlink() && Path::new(&shared_bin).join(&arg0_full_path_name).exists() {
+        arg0_full_path_name
+    } else {
+        sharun.file_name().unwrap_or_default().to_string_lossy().to_string()
+    };
+    drop(arg0_dir);
+    drop(arg0_full_path);
+
+    let mut is_stdin_args = false;
+    let mut is_print_loader_cmd = false;
+    let mut is_dry_run = false;
+    let mut is_verify = false;
+    let mut env_trace: EnvTrace = None;
+    #[cfg(feature = "testing")]
+    let mut is_emit_plan_json = false;
+    let mut print_env_key: Option<String> = None;
+    let mut strip_args_count = 0;
+    let mut bisect_libs: Vec<String> = Vec::new();
+    let mut prepend_paths: Vec<String> = Vec::new();
+    let mut append_paths: Vec<String> = Vec::new();
+    if bin_name == SHARUN_NAME {
+        while let Some(arg) = exec_args.first() {
+            match arg.as_str() {
+                "--stdin-args" => {
+                    is_stdin_args = true;
+                    exec_args.remove(0);
+                }
+                "--print-loader-cmd" => {
+                    is_print_loader_cmd = true;
+                    exec_args.remove(0);
+                }
+                "--dry-run" => {
+                    is_dry_run = true;
+                    exec_args.remove(0);
+                }
+                "--verify" => {
+                    is_verify = true;
+                    exec_args.remove(0);
+                }
+                "--trace-env-sources" => {
+                    env_trace = Some((env::vars().collect(), std::collections::BTreeMap::new()));
+                    exec_args.remove(0);
+                }
+                #[cfg(feature = "testing")]
+                "--emit-plan-json" => {
+                    is_emit_plan_json = true;
+                    exec_args.remove(0);
+                }
+                "--print-env-for" => {
+                    exec_args.remove(0);
+                    if exec_args.is_empty() {
+                        return Err(Error::other("Specify the environment variable name!"))
+                    }
+                    print_env_key = Some(exec_args.remove(0))
+                }
+                "--print-env" | "--env" => {
+                    exec_args.remove(0);
+                    print_env_key = Some("".into())
+                }
+                "--bisect-libs" => {
+                    exec_args.remove(0);
+                    if exec_args.is_empty() {
+                        return Err(Error::other("Specify the comma-separated list of bundled libraries!"))
+                    }
+                    bisect_libs = exec_args.remove(0).split(',').map(String::from).collect()
+                }
+                "--prepend-path" | "--append-path" => {
+                    let arg = exec_args.remove(0);
+                    if exec_args.is_empty() {
+                        return Err(Error::other(format!("Specify the directory for {arg}!")))
+                    }
+                    if arg == "--prepend-path" {
+                        prepend_paths.push(exec_args.remove(0))
+                    } else {
+                        append_paths.push(exec_args.remove(0))
+                    }
+                }
+                "--strip-args" => {
+                    exec_args.remove(0);
+                    strip_args_count = exec_args.first()
+                        .and_then(|count| count.parse::<usize>().ok())
+                        .ok_or_else(|| Error::other("Specify the number of arguments to strip!"))?;
+                    exec_args.remove(0);
+                }
+                _ => break
+            }
+        }
+        if !exec_args.is_empty() {
+            match exec_args[0].as_str() {
+                "-v" | "--version" => {
+                    println!("v{}", env!("CARGO_PKG_VERSION"));
+                    return Ok(0)
+                }
+                "-l" | "--list" => {
+                    list_bins_json(bin_dir, shared_bin);
+                    return Ok(0)
+                }
+                "--version-json" => {
+                    let interpreters = get_default_interpreters().iter()
+                        .map(|interpreter| json_string(interpreter))
+                        .collect::<Vec<String>>();
+                    println!("{{
+  \"version\": {},
+  \"arch\": {},
+  \"libc\": {},
+  \"default_interpreters\": [{}],
+  \"has_lib4bin\": {}
+}}",
+                        json_string(env!("CARGO_PKG_VERSION")),
+                        json_string(std::env::consts::ARCH),
+                        json_string(if cfg!(target_env = "musl") { "musl" } else { "glibc" }),
+                        interpreters.join(", "),
+                        cfg!(feature = "lib4bin"));
+                    return Ok(0)
+                }
+                "-h" | "--help" => {
+                    print_usage();
+                    return Ok(0)
+                }
+                "-f" | "--find" => {
+                    if exec_args.len() < 2 {
+                        return Err(Error::other("Specify the file name pattern!"))
+                    }
+                    let mut is_found = false;
+                    for entry in WalkDir::new(&sharun_dir).into_iter().flatten() {
+                        if is_glob_match(&exec_args[1], &entry.file_name().to_string_lossy()) {
+                            if let Ok(path) = entry.path().strip_prefix(&sharun_dir) {
+                                println!("{}", path.display());
+                                is_found = true
+                            }
+                        }
+                    }
+                    return Ok(if is_found { 0 } else { 1 })
+                }
+                "--json-deps" => {
+                    if exec_args.len() < 2 {
+                        return Err(Error::other(format!("Specify the executable from: '{shared_bin}'")))
+                    }
+                    let bin = format!("{shared_bin}/{}", exec_args[1]);
+                    let is_elf32_bin = check_elf32(&bin)?;
+                    let lib_dirs = get_lib_dirs(&sharun_dir, if is_elf32_bin { &shared_lib32 } else { &shared_lib });
+                    let (deps, is_resolved) = get_dep_tree(Path::new(&bin), &lib_dirs);
+                    let children = json_deps(&deps, 0);
+                    println!("{{\"soname\": {}, \"path\": {}, \"children\": {children}}}",
+                        json_string(&exec_args[1]), json_string(&bin));
+                    return Ok(if is_resolved { 0 } else { 1 })
+                }
+                "--check" => {
+                    if exec_args.len() < 2 {
+                        return Err(Error::other(format!("Specify the executable from: '{shared_bin}'")))
+                    }
+                    let bin = format!("{shared_bin}/{}", exec_args[1]);
+                    let is_elf32_bin = check_elf32(&bin)?;
+                    let lib_dirs = get_lib_dirs(&sharun_dir, if is_elf32_bin { &shared_lib32 } else { &shared_lib });
+                    let (deps, is_resolved) = get_dep_tree(Path::new(&bin), &lib_dirs);
+                    if is_resolved {
+                        println!("OK");
+                        return Ok(0)
+                    }
+                    print_missing_deps(Path::new(&bin), &deps);
+                    return Ok(1)
+                }
+                "--trace-deps" => {
+                    if exec_args.len() < 2 {
+                        return Err(Error::other(format!("Specify the executable from: '{shared_bin}'")))
+                    }
+                    let bin = format!("{shared_bin}/{}", exec_args[1]);
+                    let is_elf32_bin = check_elf32(&bin)?;
+                    let lib_dirs = get_lib_dirs(&sharun_dir, if is_elf32_bin { &shared_lib32 } else { &shared_lib });
+                    println!("{bin}");
+                    let (deps, is_resolved) = get_dep_tree(Path::new(&bin), &lib_dirs);
+                    print_trace_deps(&deps, 1);
+                    return Ok(if is_resolved { 0 } else { 1 })
+                }
+                "--which" => {
+                    if exec_args.len() < 2 {
+                        return Err(Error::other("Specify the executable name!"))
+                    }
+                    let mut name = exec_args[1].clone();
+                    if let Some(alias_bin) = read_aliases(&sharun_dir)?.remove(&name) {
+                        name = alias_bin
+                    }
+                    let bin_path = PathBuf::from(bin_dir).join(&name);
+                    if let Ok(bin_full_path) = bin_path.canonicalize() {
+                        let bin_full_path_name = bin_full_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                        if bin_path.is_symlink() && Path::new(&shared_bin).join(&bin_full_path_name).exists() {
+                            name = bin_full_path_name
+                        }
+                        if is_exe(&bin_full_path) &&
+                            (is_hardlink(&sharun, &bin_full_path) ||
+                            !Path::new(&shared_bin).join(&name).exists() ||
+                            bin_full_path != sharun)
+                        {
+                            println!("{}", bin_path.display());
+                            return Ok(0)
+                        }
+                    }
+                    let shared_bin_path = PathBuf::from(&shared_bin).join(&name);
+                    if is_exe(&shared_bin_path) {
+                        println!("{}", shared_bin_path.display());
+                        return Ok(0)
+                    }
+                    return Err(Error::other(format!("Executable not found: {name}")))
+                }
+                "--ldd" => {
+                    if exec_args.len() < 2 {
+                        return Err(Error::other(format!("Specify the executable from: '{shared_bin}'")))
+                    }
+                    let bin = format!("{shared_bin}/{}", exec_args[1]);
+                    let is_elf32_bin = check_elf32(&bin)?;
+                    let library_path = if is_elf32_bin { &shared_lib32 } else { &shared_lib };
+                    let lib_dirs = get_lib_dirs(&sharun_dir, library_path);
+                    let (deps, is_resolved) = get_dep_tree(Path::new(&bin), &lib_dirs);
+                    print_ldd_deps(&deps);
+                    match get_bin_interpreter(&bin, library_path) {
+                        Ok(interpreter) => println!("\t{}", interpreter.display()),
+                        Err(_) => println!("\tinterpreter => not found")
+                    }
+                    return Ok(if is_resolved { 0 } else { 1 })
+                }
+                "--set-interp" => {
+                    if exec_args.len() < 2 {
+                        return Err(Error::other(format!("Specify the executable from: '{shared_bin}'")))
+                    }
+                    let bin = format!("{shared_bin}/{}", exec_args[1]);
+                    let is_write = exec_args.get(2).is_some_and(|arg| arg == "--write");
+                    let is_elf32_bin = check_elf32(&bin)?;
+                    let library_path = if is_elf32_bin { &shared_lib32 } else { &shared_lib };
+                    let interpreter = get_bin_interpreter(&bin, library_path)
+                        .map_err(|_| Error::other("Interpreter not found!"))?;
+                    // the kernel resolves a relative INTERP from the cwd and not from $ORIGIN,
+                    // so only an absolute path works and it pins the bundle to its current location
+                    let new_interp = interpreter.to_string_lossy();
+                    let new_runpath = get_lib_dirs(&sharun_dir, library_path).iter()
+                        .map(|lib_dir| lib_dir.replacen(library_path.as_str(),
+                            &format!("$ORIGIN/../{}", basename(library_path)), 1))
+                        .collect::<Vec<String>>()
+                        .join(":");
+                    let mut elf_bytes = Vec::new();
+                    if let Err(err) = File::open(&bin).and_then(|mut file| file.read_to_end(&mut elf_bytes)) {
+                        return Err(Error::other(format!("Failed to read ELF: {bin}: {err}")))
+                    }
+                    let current_interp = get_elf_interp(&elf_bytes)
+                        .map_err(|err| Error::other(format!("Failed to get ELF interpreter: {bin}: {err}")))?;
+                    let is_interp_fits = new_interp.len() <= current_interp.len();
+                    println!("INTERP: {current_interp} => {new_interp} ({})",
+                        if is_interp_fits { "fits" } else { "too long" });
+                    match get_runpath_slot(&elf_bytes) {
+                        Ok((_, current_runpath)) => {
+                            println!("RUNPATH: {current_runpath} => {new_runpath} ({})",
+                                if new_runpath.len() <= current_runpath.len() { "fits" } else { "too long" });
+                        }
+                        Err(err) => println!("RUNPATH: {err}")
+                    }
+                    if is_write {
+                        eprintln!("WARNING: INTERP is an absolute path, the patched executable only works \
+                            while the bundle is at: {sharun_dir}");
+                        if let Err(err) = set_runpath(&mut elf_bytes, &new_runpath) {
+                            eprintln!("WARNING: Failed to set ELF RUNPATH: {bin}: {err}")
+                        }
+                        set_interp(elf_bytes, &bin, &new_interp)
+                            .map_err(|err| Error::other(format!("Failed to set ELF interpreter: {bin}: {err}")))?;
+                        println!("Patched: {bin}")
+                    }
+                    return Ok(0)
+                }
+                "--clean-rpath" => {
+                    if exec_args.len() < 2 {
+                        return Err(Error::other(format!("Specify the executable from: '{shared_bin}'")))
+                    }
+                    let bin = format!("{shared_bin}/{}", exec_args[1]);
+                    let is_write = exec_args.get(2).is_some_and(|arg| arg == "--write");
+                    let mut elf_bytes = Vec::new();
+                    if let Err(err) = File::open(&bin).and_then(|mut file| file.read_to_end(&mut elf_bytes)) {
+                        return Err(Error::other(format!("Failed to read ELF: {bin}: {err}")))
+                    }
+                    let (_, current_runpath) = get_runpath_slot(&elf_bytes)
+                        .map_err(|err| Error::other(format!("Failed to get ELF RUNPATH: {bin}: {err}")))?;
+                    let (keep_entries, drop_entries): (Vec<&str>, Vec<&str>) = current_runpath
+                        .split(':').filter(|entry| !entry.is_empty())
+                        .partition(|entry| !entry.starts_with('/') ||
+                            Path::new(entry).starts_with(&sharun_dir));
+                    for entry in &drop_entries {
+                        println!("Remove: {entry}")
+                    }
+                    let new_runpath = keep_entries.join(":");
+                    println!("RUNPATH: {current_runpath} => {new_runpath}");
+                    if is_write && !drop_entries.is_empty() {
+                        set_runpath(&mut elf_bytes, &new_runpath)
+                            .and_then(|_| write_file(&bin, &elf_bytes))
+                            .map_err(|err| Error::other(format!("Failed to set ELF RUNPATH: {bin}: {err}")))?;
+                        println!("Patched: {bin}")
+                    }
+                    return Ok(0)
+                }
+                "--compare-bundles" => {
+                    if exec_args.len() < 3 {
+                        return Err(Error::other("Specify the other sharun directory and the executable!"))
+                    }
+                    let other_dir = realpath(&exec_args[1])
+                        .map_err(|err| Error::other(format!("Failed to find sharun directory: {}: {err}", exec_args[1])))?;
+                    return Ok(if compare_bundles(&sharun, &sharun_dir, &other_dir, &exec_args[2])? { 0 } else { 1 })
+                }
+                "--print-interpreter" => {
+                    if exec_args.len() < 2 {
+                        return Err(Error::other(format!("Specify the executable from: '{shared_bin}'")))
+                    }
+                    let bin = format!("{shared_bin}/{}", exec_args[1]);
+                    let library_path = if check_elf32(&bin)? { &shared_lib32 } else { &shared_lib };
+                    let interpreter = get_bin_interpreter(&bin, library_path)
+                        .map_err(|_| Error::other("Interpreter not found!"))?;
+                    println!("{}", interpreter.display());
+                    return Ok(0)
+                }
+                "--print-shared-bin" => {
+                    if exec_args.len() < 2 {
+                        return Err(Error::other(format!("Specify the executable from: '{shared_bin}'")))
+                    }
+                    let bin = format!("{shared_bin}/{}", exec_args[1]);
+                    let bin_full_path = Path::new(&bin).canonicalize()
+                        .map_err(|err| Error::other(format!("Failed to resolve executable: {bin}: {err}")))?;
+                    let is_elf32_bin = check_elf32(&bin)?;
+                    cfg_if! {
+                        if #[cfg(feature = "pyinstaller")] {
+                            let is_pyinstaller_elf = get_elf(&bin)
+                                .and_then(|elf_bytes| is_elf_section(&elf_bytes, "pydata"))
+                                .unwrap_or(false);
+                        } else {
+                            let is_pyinstaller_elf = false;
+                        }
+                    }
+                    println!("{}", bin_full_path.display());
+                    println!("ELF class: {}", if is_elf32_bin { "ELF32" } else { "ELF64" });
+                    println!("PyInstaller: {}", if is_pyinstaller_elf { "yes" } else { "no" });
+                    return Ok(0)
+                }
+                "--normalize-rpaths" => {
+                    let is_write = exec_args.get(1).is_some_and(|arg| arg == "--write");
+                    return Ok(if normalize_rpaths(&sharun_dir, shared_dir, is_write) { 0 } else { 1 })
+                }
+                "--check-abi" => {
+                    if exec_args.len() < 2 {
+                        return Err(Error::other(format!("Specify the executable from: '{shared_bin}'")))
+                    }
+                    let bin = format!("{shared_bin}/{}", exec_args[1]);
+                    let is_elf32_bin = check_elf32(&bin)?;
+                    let library_path = if is_elf32_bin { &shared_lib32 } else { &shared_lib };
+                    let lib_dirs = get_lib_dirs(&sharun_dir, library_path);
+                    return Ok(if check_abi(&bin, library_path, &lib_dirs) { 0 } else { 1 })
+                }
+                "--dump-desktop" => {
+                    dump_desktop(&sharun_dir)?;
+                    return Ok(0)
+                }
+                "--count-libs" => {
+                    for library_path in [&shared_lib, &shared_lib32] {
+                        if Path::new(library_path).exists() {
+                            println!("[ {library_path} ]");
+                            count_libs(&get_lib_dirs(&sharun_dir, library_path))
+                        }
+                    }
+                    return Ok(0)
+                }
+                "--exec-self-test" => {
+                    return Ok(if exec_self_test(&sharun, shared_bin) { 0 } else { 1 })
+                }
+                "--gen-qt-conf" => {
+                    gen_qt_conf(&sharun_dir, &shared_lib, &format!("{bin_dir}/qt.conf"))?;
+                    return Ok(0)
+                }
+                "--export-manifest" => {
+                    let manifest_file = &exec_args.get(1).cloned()
+                        .unwrap_or_else(|| format!("{sharun_dir}/.manifest"));
+                    export_manifest(&sharun_dir, manifest_file)?;
+                    return Ok(0)
+                }
+                "-g" | "--gen-lib-path" => {
+                    if let Some(library_path) = exec_args.get(1) {
+                        if !is_dir(library_path) {
+                            return Err(Error::other(format!("Directory not found: {library_path}")))
+                        }
+                        let library_path = realpath(library_path)
+                            .map_err(|err| Error::other(format!("Failed to resolve directory: {library_path}: {err}")))?;
+                        if !is_writable(&library_path) {
+                            return Err(Error::other(format!("Directory is not writable: {library_path}")))
+                        }
+                        gen_library_path(&library_path, &format!("{library_path}/lib.path"))?;
+                        return Ok(0)
+                    }
+                    for library_path in [shared_lib, shared_lib32] {
+                        if Path::new(&library_path).exists() {
+                            let lib_path_file = &format!("{library_path}/lib.path");
+                            gen_library_path(&library_path, lib_path_file)?
+                        }
+                    }
+                    return Ok(0)
+                }
+                "--gen-bins" => {
+                    let exclude: Vec<&str> = match exec_args.get(1).map(String::as_str) {
+                        Some("--exclude") => exec_args.get(2).map(|names| names.split(',').collect()).unwrap_or_default(),
+                        _ => vec![]
+                    };
+                    let sharun_path = Path::new(&sharun_dir).join(SHARUN_NAME);
+                    let sharun_path = if is_exe(&sharun_path) { sharun_path } else { sharun.clone() };
+                    if let Err(err) = gen_bins(&sharun_path, bin_dir, shared_bin, &exclude) {
+                        return Err(Error::other(format!("Failed to create symlinks in: {bin_dir}: {err}")))
+                    }
+                    return Ok(0)
+                }
+                "--migrate-lib-path" => {
+                    for library_path in [shared_lib, shared_lib32] {
+                        let lib_path_file = &format!("{library_path}/lib.path");
+                        if Path::new(lib_path_file).exists() {
+                            migrate_library_path(&library_path, lib_path_file)?
+                        }
+                    }
+                    return Ok(0)
+                }
+                #[cfg(feature = "lib4bin")]
+                "l" | "lib4bin" => {
+                    cfg_if! {
+                        if #[cfg(feature = "zstd-lib4bin")] {
+                            let lib4bin_compressed = include_bytes!(concat!(env!("OUT_DIR"), "/lib4bin.zst"));
+                            let lib4bin = zstd::decode_all(&lib4bin_compressed[..]).unwrap_or_default();
+                        } else {
+                            let lib4bin_compressed = include_file_compress::include_file_compress_deflate!("lib4bin", 9);
+                            let mut decoder = flate2::read::DeflateDecoder::new(&lib4bin_compressed[..]);
+                            let mut lib4bin = Vec::new();
+                            decoder.read_to_end(&mut lib4bin).unwrap_or_default();
+                            drop(decoder);
+                        }
+                    }
+                    exec_args.remove(0);
+                    add_to_env("PATH", bin_dir);
+                    let cmd = Command::new("bash")
+                        .env("SHARUN", sharun)
+                        .stdin(std::process::Stdio::piped())
+                        .arg("-s").arg("--")
+                        .args(exec_args)
+                        .spawn();
+                    match cmd {
+                        Ok(mut bash) => {
+                            bash.stdin.take()
+                                .ok_or_else(|| Error::other("Failed to take bash stdin!"))?
+                                .write_all(&lib4bin)
+                                .map_err(|err| Error::other(format!("Failed to write lib4bin to bash stdin: {err}")))?;
+                            return Ok(bash.wait().unwrap_or_default().code().unwrap_or_default())
+                        }
+                        Err(err) => {
+                            return Err(Error::other(format!("Failed to run bash: {err}")))
+                        }
+                    }
+                }
+                _ => {
+                    bin_name = exec_args.remove(0);
+                    if let Some(alias_bin) = read_aliases(&sharun_dir)?.remove(&bin_name) {
+                        bin_name = alias_bin
+                    }
+                    exec_args.drain(..strip_args_count.min(exec_args.len()));
+                    let bin_path = PathBuf::from(bin_dir).join(&bin_name);
+                    if let Ok(bin_full_path) = bin_path.canonicalize() {
+                        let bin_full_path_name = bin_full_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                        if bin_path.is_symlink() && Path::new(&shared_bin).join(&bin_full_path_name).exists() {
+                            bin_name = bin_full_path_name
+                        }
+                        if is_exe(&bin_full_path) &&
+                            (is_hardlink(&sharun, &bin_full_path) ||
+                            !Path::new(&shared_bin).join(&bin_name).exists() ||
+                            bin_full_path != sharun)
+                        {
+                            add_to_env("PATH", bin_dir);
+                            update_path(&prepend_paths, &append_paths);
+                            if let Some(key) = print_env_key {
+                                print_env(&key);
+                                return Ok(0)
+                            }
+                            if is_print_loader_cmd || is_dry_run {
+                                let mut command = vec![bin_path.to_string_lossy().to_string()];
+                                command.extend(exec_args);
+                                if is_dry_run { print_dry_run(&command) } else { print_command(&command) }
+                                return Ok(0)
+                            }
+                            match is_script(&bin_path) {
+                                Ok(true) => {
+                                    if is_stdin_args {
+                                        return run_stdin_args(||{
+                                            let mut command = script_command(&bin_path)
+                                                .map_err(|err| Error::other(format!("Error executing script: {err}")))?;
+                                            command.args(&exec_args);
+                                            Ok(command)
+                                        })
+                                    }
+                                    if let Err(err) = exec_script(&bin_path, &exec_args) {
+                                        return Err(Error::other(format!("Error executing script: {err}")))
+                                    }
+                                }
+                                Ok(false) => {
+                                    if is_stdin_args {
+                                        return run_stdin_args(||{
+                                            let mut command = Command::new(&bin_path);
+                                            command.args(&exec_args);
+                                            Ok(command)
+                                        })
+                                    }
+                                    let err = Command::new(&bin_path)
+                                        .args(exec_args)
+                                        .exec();
+                                    return Err(Error::other(format!("Error executing file {:?}: {err}", &bin_path)))
+                                }
+                                Err(err) => {
+                                    return Err(Error::other(format!("Error reading file {:?}: {err}", &bin_path)))
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        } else {
+            print_error(&format!("Specify the executable from: '{bin_dir}'"));
+            if let Ok(dir) = Path::new(bin_dir).read_dir() {
+                for bin in dir.flatten() {
+                    if is_exe(&bin.path()) {
+                        println!("{}", bin.file_name().to_str().unwrap_or_default())
+                    }
+                }
+            }
+            return Ok(1)
+        }
+    } else if bin_name == "AppRun" {
+        let appname_file = &format!("{sharun_dir}/.app");
+        let mut appname: String = "".into();
+        if !Path::new(appname_file).exists() {
+            if let Some(desktop_file) = find_desktop_file(&sharun_dir) {
+                appname = read_desktop_key(&desktop_file, "Desktop Entry", "Exec")?
+                    .split_whitespace().next().unwrap_or("").into()
+            }
+        }
+
+        if exec_args.first().is_some_and(|arg| arg == "--action") {
+            exec_args.remove(0);
+            if exec_args.is_empty() {
+                return Err(Error::other("Specify the desktop action name!"))
+            }
+            let action = exec_args.remove(0);
+            let action_exec = find_desktop_file(&sharun_dir)
+                .map(|desktop_file| read_desktop_key(&desktop_file, &format!("Desktop Action {action}"), "Exec"))
+                .transpose()?
+                .unwrap_or_default();
+            if action_exec.is_empty() {
+                return Err(Error::other(format!("Failed to find desktop action: {action}")))
+            }
+            let action_command = strip_field_codes(&action_exec);
+            let mut action_args = split_args(&action_command);
+            if action_args.is_empty() {
+                return Err(Error::other(format!("Empty Exec of desktop action: {action}")))
+            }
+            appname = action_args.remove(0);
+            action_args.append(&mut exec_args);
+            exec_args = action_args
+        }
+
+        if appname.is_empty() {
+            appname = read_to_string(appname_file)
+                .map_err(|err| Error::other(format!("Failed to read .app file: {appname_file}: {err}")))?
+        }
+
+        if let Some(name) = appname.trim().split("\n").next() {
+            appname = get_appname(name)
+        } else {
+            return Err(Error::other(format!("Failed to get app name: {appname_file}")))
+        }
+        let app = &format!("{bin_dir}/{appname}");
+
+        if let Ok(launch_dir) = env::current_dir() {
+            for arg in exec_args.iter_mut() {
+                let arg_path = Path::new(arg.as_str());
+                if !arg.starts_with('-') && arg_path.is_relative() && arg_path.exists() {
+                    *arg = launch_dir.join(arg_path).to_string_lossy().into()
+                }
+            }
+        }
+
+        add_to_env("PATH", bin_dir);
+        if get_env_var("ARGV0").is_empty() {
+            env::set_var("ARGV0", &arg0)
+        }
+        if get_env_var("APPDIR").is_empty() {
+            env::set_var("APPDIR", &sharun_dir)
+        }
+
+        let err = Command::new(app)
+            .args(exec_args)
+            .exec();
+        return Err(Error::other(format!("Failed to run App: {app}: {err}")))
+    }
+    let bin = format!("{shared_bin}/{bin_name}");
+
+    if Path::new(&bin).metadata().is_ok_and(|metadata| metadata.mode() & 0o6000 != 0) {
+        if get_env_var("SHARUN_STRICT") == "1" {
+            return Err(Error::other(format!("{bin} has setuid/setgid bit, refusing to run it with bundled libraries!")))
+        }
+        eprintln!("WARNING: {bin} has setuid/setgid bit, it will be run without elevated privileges!")
+    }
+
+    let is_elf32_bin = check_elf32(&bin)?;
+    env::remove_var("SHARUN_FORCE_LIB32");
+    env::remove_var("SHARUN_FORCE_LIB64");
+
+    cfg_if! {
+        if #[cfg(feature = "pyinstaller")] {
+            let elf_bytes = get_elf(&bin).map_err(|err| Error::other(format!("Failed to read ELF: {}: {err}", &bin)))?;
+        } else {
+            let elf_bytes: Vec<u8> = vec![];
+        }
+    }
+
+    let library_path = if is_elf32_bin {
+        shared_lib32
+    } else {
+        shared_lib
+    };
+
+    cfg_if! {
+        if #[cfg(feature = "testing")] {
+            let is_launch = !is_print_loader_cmd && !is_dry_run && !is_emit_plan_json;
+        } else {
+            let is_launch = !is_print_loader_cmd && !is_dry_run;
+        }
+    }
+
+    // before anything from the bundle is run or trusted, lib.path included
+    if is_verify && is_launch {
+        let manifest_file = &format!("{sharun_dir}/.manifest");
+        match verify_manifest(&sharun_dir, manifest_file, &library_path) {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err(Error::other(format!("Bundled libraries do not match the manifest: {manifest_file}")))
+            }
+            Err(err) => {
+                return Err(Error::other(format!("Failed to read manifest: {manifest_file}: {err}")))
+            }
+        }
+    }
+
+    let env_whitelist = get_env_var("SHARUN_ENV_WHITELIST");
+    if !env_whitelist.is_empty() {
+        let env_whitelist: Vec<&str> = env_whitelist.split(':').collect();
+        for (key, _) in env::vars_os() {
+            let key = key.to_string_lossy();
+            // keep sharun config and the vars set by AppRun
+            if !key.starts_with("SHARUN_") && key != "ARGV0" && key != "APPDIR" &&
+                !env_whitelist.contains(&key.as_ref()) {
+                env::remove_var(key.as_ref())
+            }
+        }
+    }
+    env::remove_var("SHARUN_ENV_WHITELIST");
+
+    trace_env(&mut env_trace, "sharun");
+    let unset_envs = read_dotenv(&sharun_dir)?;
+    trace_env(&mut env_trace, "dotenv");
+
+    // its entries always go to --preload, SHARUN_ALLOW_LD_PRELOAD only decides if the children inherit it
+    let ld_preload = get_env_var("LD_PRELOAD");
+    if get_env_var("SHARUN_ALLOW_LD_PRELOAD") != "1" {
+        env::remove_var("LD_PRELOAD")
+    }
+    env::remove_var("SHARUN_ALLOW_LD_PRELOAD");
+
+    if get_env_var("SHARUN_ALLOW_QT_PLUGIN_PATH") != "1" {
+        env::remove_var("QT_PLUGIN_PATH")
+    }
+    env::remove_var("SHARUN_ALLOW_QT_PLUGIN_PATH");
+
+    let is_background = get_env_var("SHARUN_BACKGROUND") == "1";
+    env::remove_var("SHARUN_BACKGROUND");
+
+    let is_dbus_isolated = get_env_var("SHARUN_DBUS") == "isolated";
+    env::remove_var("SHARUN_DBUS");
+
+    let freetype_properties = get_env_var("SHARUN_FREETYPE_PROPERTIES");
+    if !freetype_properties.is_empty() && get_env_var("FREETYPE_PROPERTIES").is_empty() {
+        env::set_var("FREETYPE_PROPERTIES", freetype_properties)
+    }
+    env::remove_var("SHARUN_FREETYPE_PROPERTIES");
+
+    if get_env_var("SHARUN_NO_A11Y") == "1" {
+        env::set_var("NO_AT_BRIDGE", "1");
+        env::set_var("GTK_A11Y", "none")
+    }
+    env::remove_var("SHARUN_NO_A11Y");
+
+    match get_env_var("SHARUN_ISOLATE_SETTINGS").as_str() {
+        "1" => env::set_var("GSETTINGS_BACKEND", "memory"),
+        "keyfile" => env::set_var("GSETTINGS_BACKEND", "keyfile"),
+        _ => {}
+    }
+    env::remove_var("SHARUN_ISOLATE_SETTINGS");
+
+    let interpreter = get_bin_interpreter(&bin, &library_path).map_err(|_| Error::other("Interpreter not found!"))?;
+
+    let working_dir = &get_env_var("SHARUN_WORKING_DIR");
+    if !working_dir.is_empty() {
+        env::set_current_dir(working_dir)
+            .map_err(|err| Error::other(format!("Failed to change working directory: {working_dir}: {err}")))?;
+        env::remove_var("SHARUN_WORKING_DIR")
+    }
+
+    trace_env(&mut env_trace, "sharun");
+
+    let sharun_library_path = get_env_var("SHARUN_LIBRARY_PATH");
+    env::remove_var("SHARUN_LIBRARY_PATH");
+
+    let is_no_lib_path = get_env_var("SHARUN_NO_LIB_PATH") == "1";
+    env::remove_var("SHARUN_NO_LIB_PATH");
+
+    let lib_path_file = &get_lib_path_file(&sharun_dir, &library_path);
+    let mut gen_lib_path_data = None;
+    if is_no_lib_path && sharun_library_path.is_empty() && Path::new(&library_path).is_dir() {
+        gen_lib_path_data = Some(get_library_path_data(&library_path))
+    } else if sharun_library_path.is_empty() && Path::new(&library_path).is_dir() &&
+        is_library_path_stale(&library_path, lib_path_file) {
+        let lib_path_dir = dirname(lib_path_file);
+        if is_writable(&lib_path_dir) || std::fs::create_dir_all(&lib_path_dir).is_ok() {
+            gen_library_path(&library_path, lib_path_file)?
+        } else {
+            gen_lib_path_data = Some(get_library_path_data(&library_path))
+        }
+    }
+
+    add_to_env("PATH", bin_dir);
+
+    let lib_path_data = if let Some(lib_path_data) = gen_lib_path_data {
+        lib_path_data
+    } else if sharun_library_path.is_empty() {
+        log_scan_err(Path::new(lib_path_file), read_to_string(lib_path_file)).unwrap_or_default()
+    } else {
+        // the env setup below only sees the bundled dirs from SHARUN_LIBRARY_PATH
+        sharun_library_path.split(':')
+            .filter(|lib_dir| lib_dir.starts_with(&library_path))
+            .map(|lib_dir| lib_dir.replacen(&library_path, "+", 1))
+            .collect::<Vec<String>>()
+            .join("\n")
+    };
+
+    trace_env(&mut env_trace, "sharun");
+    #[cfg(feature = "setenv")]
+    {
+        setup_lib_env(&sharun_dir, &library_path, &lib_path_data)?;
+        trace_env(&mut env_trace, "lib-detection");
+        setup_share_env(&sharun_dir)?;
+        trace_env(&mut env_trace, "share-scan");
+        setup_etc_env(&sharun_dir)?;
+        trace_env(&mut env_trace, "etc-scan");
+    }
+
+    let library_path = resolve_library_path(&sharun_dir, &library_path, &lib_path_data, is_elf32_bin,
+        &sharun_library_path, &bisect_libs)?;
+    drop(lib_path_data);
+
+    trace_env(&mut env_trace, "sharun");
+    for var_name in unset_envs {
+        env::remove_var(var_name)
+    }
+    trace_env(&mut env_trace, "dotenv");
+
+    update_path(&prepend_paths, &append_paths);
+    trace_env(&mut env_trace, "cli");
+
+    if get_env_var("SHARUN_KEEP_LD_ENV") != "1" {
+        // LD_LIBRARY_PATH is already passed to the interpreter with --library-path
+        // and LD_PRELOAD is only left here if SHARUN_ALLOW_LD_PRELOAD=1
+        let ld_envs: Vec<String> = env::vars_os()
+            .map(|(key, _)| key.to_string_lossy().to_string())
+            .filter(|key| key.starts_with("LD_") && key != "LD_PRELOAD")
+            .collect();
+        for var_name in ld_envs {
+            env::remove_var(var_name)
+        }
+    }
+    env::remove_var("SHARUN_KEEP_LD_ENV");
+
+    if get_env_var("SHARUN_PRINTENV") == "1" {
+        env::remove_var("SHARUN_PRINTENV");
+        for (key, value) in env::vars_os() {
+            eprintln!("{}={}", key.to_string_lossy(), value.to_string_lossy())
+        }
+    }
+
+    if let Some(key) = print_env_key {
+        print_env(&key);
+        return Ok(0)
+    }
+
+    if env_trace.is_some() {
+        trace_env(&mut env_trace, "sharun");
+        print_env_sources(env_trace);
+        return Ok(0)
+    }
+
+    let hook = Path::new(&sharun_dir).join(".hook");
+    if is_exe(&hook) && is_launch {
+        let is_strict = get_env_var("SHARUN_STRICT") == "1";
+        match Command::new(&hook).env("SHARUN_BIN_NAME", &bin_name).status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => {
+                eprintln!("{}: hook {}: {status}",
+                    if is_strict { "ERROR" } else { "WARNING" }, hook.display());
+                if is_strict { return Ok(status.code().unwrap_or(1)) }
+            }
+            Err(err) => {
+                eprintln!("{}: Failed to run hook: {}: {err}",
+                    if is_strict { "ERROR" } else { "WARNING" }, hook.display());
+                if is_strict { return Ok(1) }
+            }
+        }
+    }
+
+    let unshare_namespaces = get_env_var("SHARUN_UNSHARE");
+    if !unshare_namespaces.is_empty() && is_launch {
+        env::remove_var("SHARUN_UNSHARE");
+        unshare_ns(&sharun_dir, &unshare_namespaces)
+            .map_err(|err| Error::other(format!("Failed to unshare namespaces: {unshare_namespaces}: {err}")))?
+    }
+
+    cfg_if! {
+        if #[cfg(feature = "pyinstaller")] {
+            let is_pyinstaller_elf = is_elf_section(&elf_bytes, "pydata").unwrap_or(false);
+            let is_pyinstaller_dir = Path::new(&shared_bin).join("_internal").exists();
+        } else {
+            let is_pyinstaller_elf = false;
+            let is_pyinstaller_dir = false;
+        }
+    }
+
+    let interpreter_args = if !is_pyinstaller_elf || is_pyinstaller_dir || is_elf32_bin {
+        let argv0 = if is_pyinstaller_elf || is_elf32_bin { &bin } else { arg0_path.to_str().unwrap_or_default() };
+        get_interpreter_args(&sharun_dir, &interpreter, &library_path, &bin, argv0, &exec_args, &ld_preload)?
+    } else {
+        Vec::new()
+    };
+
+    let get_launch_argv = || -> Vec<String> {
+        if interpreter_args.is_empty() {
+            [bin.clone()].into_iter().chain(exec_args.iter().cloned()).collect()
+        } else {
+            interpreter_args.iter().map(|s| s.to_string_lossy().to_string()).collect()
+        }
+    };
+
+    #[cfg(feature = "testing")]
+    if is_emit_plan_json {
+        let mode = if !is_pyinstaller_elf && !is_elf32_bin {
+            "userland_execve"
+        } else if is_pyinstaller_dir || !is_pyinstaller_elf {
+            "exec"
+        } else {
+            "set_interp"
+        };
+        println!("{}", get_plan_json(&initial_env, &interpreter, &library_path, &get_launch_argv(), mode));
+        return Ok(0)
+    }
+
+    if is_print_loader_cmd {
+        print_command(&get_launch_argv());
+        return Ok(0)
+    }
+
+    if is_dry_run {
+        print_dry_run(&get_launch_argv());
+        return Ok(0)
+    }
+
+    if is_stdin_args {
+        let (program, args) = if is_pyinstaller_elf && !is_pyinstaller_dir {
+            set_interp(elf_bytes.to_vec(), &bin, interpreter.to_str().unwrap_or_default())
+                .map_err(|err| Error::other(format!("Failed to set ELF interpreter: {bin}: {err}")))?;
+            (PathBuf::from(&bin), exec_args)
+        } else {
+            drop(elf_bytes);
+            (interpreter, interpreter_args.iter()
+                .map(|s| s.clone().into_string().unwrap_or_default()).skip(1).collect())
+        };
+        return run_stdin_args(||{
+            let mut command = Command::new(&program);
+            command.args(&args);
+            Ok(command)
+        })
+    }
+
+    if is_verbose {
+        eprintln!("sharun: interpreter: {}", interpreter.display());
+        eprintln!("sharun: library_path: {library_path}");
+        print_env_changes(&verbose_env);
+        eprintln!("sharun: interpreter_args: {:?}", interpreter_args);
+    }
+
+    if is_background {
+        match unsafe { fork() } {
+            Ok(ForkResult::Parent { .. }) => return Ok(0),
+            Ok(ForkResult::Child) => { setsid().ok(); }
+            Err(err) => {
+                return Err(Error::other(format!("Failed to fork: {err}")))
+            }
+        }
+    }
+
+    if is_dbus_isolated {
+        let dbus_daemon = Path::new(bin_dir).join("dbus-daemon");
+        if !is_exe(&dbus_daemon) {
+            return Err(Error::other(format!("dbus-daemon is not bundled: {}", dbus_daemon.display())))
+        }
+        let (mut dbus, address) = start_dbus_session(&sharun_dir, &dbus_daemon)
+            .map_err(|err| Error::other(format!("Failed to start D-Bus session bus: {err}")))?;
+        env::set_var("DBUS_SESSION_BUS_ADDRESS", address);
+        // the parent waits for the executable to stop the bus after it exits
+        match unsafe { fork() } {
+            Ok(ForkResult::Parent { child }) => {
+                // forward the termination signals so the bus is still stopped after the executable exits
+                DBUS_CHILD_PID.store(child.as_raw(), std::sync::atomic::Ordering::SeqCst);
+                let action = SigAction::new(SigHandler::Handler(forward_signal), SaFlags::SA_RESTART, SigSet::empty());
+                for signal in [Signal::SIGTERM, Signal::SIGINT, Signal::SIGHUP] {
+                    unsafe { sigaction(signal, &action) }.ok();
+                }
+                let status = waitpid(child, None);
+                dbus.kill().ok();
+                dbus.wait().ok();
+                return Ok(match status {
+                    Ok(WaitStatus::Exited(_, code)) => code,
+                    Ok(WaitStatus::Signaled(_, signal, _)) => 128 + signal as i32,
+                    _ => 1
+                })
+            }
+            Ok(ForkResult::Child) => {}
+            Err(err) => {
+                return Err(Error::other(format!("Failed to fork: {err}")))
+            }
+        }
+    }
+
+    if is_pyinstaller_elf || is_elf32_bin {
+        let err = if is_pyinstaller_dir || (!is_pyinstaller_elf && is_elf32_bin) {
+            drop(elf_bytes);
+            let interpreter_args: Vec<String> = interpreter_args.iter()
+                .map(|s| s.clone().into_string().unwrap_or_default()).skip(1).collect();
+            Command::new(interpreter)
+                .args(interpreter_args)
+                .exec()
+        } else {
+            set_interp(elf_bytes.to_vec(), &bin, interpreter.to_str().unwrap_or_default())
+                .map_err(|err| Error::other(format!("Failed to set ELF interpreter: {}: {err}", &bin)))?;
+            Command::new(&bin)
+                .args(exec_args)
+                .exec()
+        };
+        Err(Error::other(format!("Failed to exec: {bin}: {err}")))
+    } else {
+        drop(elf_bytes);
+        let proot = get_env_var("SHARUN_PROOT");
+        if !proot.is_empty() {
+            env::remove_var("SHARUN_PROOT");
+            let proot = if proot != "1" {
+                PathBuf::from(proot)
+            } else {
+                let bundled_proot = Path::new(bin_dir).join("proot");
+                if is_exe(&bundled_proot) { bundled_proot } else { PathBuf::from("proot") }
+            };
+            let interpreter_args: Vec<String> = interpreter_args.iter()
+                .map(|s| s.clone().into_string().unwrap_or_default()).collect();
+            let err = Command::new(&proot)
+                .arg("-b").arg(&sharun_dir)
+                .args(interpreter_args)
+                .exec();
+            return Err(Error::other(format!("Failed to exec proot: {}: {err}", proot.display())))
+        }
+        let envs: Vec<CString> = env::vars_os()
+            .map(|(key, value)| CString::new(
+                format!("{}={}", key.to_string_lossy(), value.to_string_lossy())
+        ).unwrap_or_default()).collect();
+
+        userland_execve::exec(
+            interpreter.as_path(),
+            &interpreter_args,
+            &envs,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("sharun-test-{name}-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_expand_env_vars() {
+        env::set_var("SHARUN_TEST_VAR", "value");
+        env::remove_var("SHARUN_TEST_UNSET");
+        assert_eq!(expand_env_vars("$SHARUN_TEST_VAR/dir"), "value/dir");
+        assert_eq!(expand_env_vars("${SHARUN_TEST_VAR}dir"), "valuedir");
+        assert_eq!(expand_env_vars("a${SHARUN_TEST_UNSET}b"), "ab");
+        assert_eq!(expand_env_vars("cost $ 5 $"), "cost $ 5 $");
+        assert_eq!(expand_env_vars("no vars"), "no vars");
+    }
+
+    #[test]
+    fn test_strip_inline_comment() {
+        assert_eq!(strip_inline_comment("KEY=value # comment"), "KEY=value");
+        assert_eq!(strip_inline_comment("KEY=value#not-comment"), "KEY=value#not-comment");
+        assert_eq!(strip_inline_comment("KEY=\"a # b\" # comment"), "KEY=\"a # b\"");
+        assert_eq!(strip_inline_comment("KEY='a # b'"), "KEY='a # b'");
+        assert_eq!(strip_inline_comment("# comment"), "");
+    }
+
+    #[test]
+    fn test_is_glob_match() {
+        assert!(is_glob_match("*.so", "libfoo.so"));
+        assert!(is_glob_match("lib?.so", "liba.so"));
+        assert!(is_glob_match("a*b*c", "axxbyyc"));
+        assert!(is_glob_match("*", ""));
+        assert!(!is_glob_match("*.so", "libfoo.so.1"));
+        assert!(!is_glob_match("?", ""));
+        assert!(!is_glob_match("lib?.so", "libab.so"));
+    }
+
+    #[test]
+    fn test_expand_glob() {
+        let dir = temp_dir("glob");
+        for path in ["a/x.so", "b/y.so", "b/z.txt"] {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            write(path, "").unwrap();
+        }
+        let base_dir = dir.to_string_lossy();
+        assert_eq!(expand_glob(&base_dir, "*/*.so"), vec![
+            format!("{base_dir}/a/x.so"),
+            format!("{base_dir}/b/y.so")
+        ]);
+        assert_eq!(expand_glob(&base_dir, "b/*.txt"), vec![format!("{base_dir}/b/z.txt")]);
+        assert_eq!(expand_glob(&base_dir, "*/*.none"), vec!["*/*.none"]);
+        assert_eq!(expand_glob(&base_dir, "a/x.so"), vec!["a/x.so"]);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_strip_field_codes() {
+        assert_eq!(strip_field_codes("app %U --flag"), "app --flag");
+        assert_eq!(strip_field_codes("app --rate=100%% %f"), "app --rate=100%");
+        assert_eq!(strip_field_codes("app"), "app");
+    }
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(split_args(r#"app "a b" 'c d' e\ f "g\"h""#), vec!["app", "a b", "c d", "e f", "g\"h"]);
+        assert_eq!(split_args("  app   --flag  "), vec!["app", "--flag"]);
+        assert_eq!(split_args(r#"app """#), vec!["app", ""]);
+        assert!(split_args("   ").is_empty());
+    }
+
+    #[cfg(feature = "setenv")]
+    #[test]
+    fn test_read_lib_rules() {
+        let dir = temp_dir("rules");
+        let library_path = dir.to_string_lossy();
+        let default_rules = read_lib_rules(&library_path).unwrap();
+        assert!(default_rules.iter().any(|rule| rule.pattern == "gstreamer-" && rule.is_prefix));
+
+        write(dir.join("lib.rules"), "\
+            # comment\n\
+            prefix=gstreamer- ; add_env=GST_PLUGIN_PATH:$dir\n\
+            name=mylib ; env?=MYLIB_PATH:$dir/plugins ; env=MYLIB_HOME:$sharun_dir\n\
+            name=nothing\n").unwrap();
+        let lib_rules = read_lib_rules(&library_path).unwrap();
+        let gstreamer: Vec<&LibRule> = lib_rules.iter().filter(|rule| rule.pattern == "gstreamer-").collect();
+        assert_eq!(gstreamer.len(), 1);
+        assert_eq!(gstreamer[0].envs.len(), 1);
+        assert!(gstreamer[0].envs[0].is_add);
+        let mylib = lib_rules.iter().find(|rule| rule.pattern == "mylib").unwrap();
+        assert!(!mylib.is_prefix);
+        assert_eq!((mylib.envs[0].key.as_str(), mylib.envs[0].val.as_str()), ("MYLIB_PATH", "$dir/plugins"));
+        assert!(mylib.envs[0].is_if_exists && !mylib.envs[1].is_if_exists);
+        assert!(!lib_rules.iter().any(|rule| rule.pattern == "nothing"));
+        assert_eq!(lib_rules.len(), default_rules.len() + 1);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn test_plan_json() {
+        let dir = temp_dir("plan");
+        let sharun_dir = dir.to_string_lossy();
+        let library_path = format!("{sharun_dir}/shared/lib");
+        let interpreter_name = &get_default_interpreters()[0];
+        for path in ["shared/bin/app", &format!("shared/lib/{interpreter_name}"), "shared/lib/foo/libfoo.so"] {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            write(path, "").unwrap();
+        }
+        write(dir.join(".preload"), "shared/lib/foo/*.so\n").unwrap();
+        let bin = format!("{sharun_dir}/shared/bin/app");
+        let initial_env: std::collections::BTreeMap<String, String> = env::vars().collect();
+
+        let interpreter = get_bin_interpreter(&bin, &library_path).unwrap();
+        assert_eq!(interpreter, Path::new(&library_path).join(interpreter_name));
+        let launch_library_path = resolve_library_path(&sharun_dir, &library_path, "+\n+/foo\n",
+            false, "", &[]).unwrap();
+        assert!(launch_library_path.starts_with(&format!("{library_path}:{library_path}/foo:")));
+        let argv: Vec<String> = get_interpreter_args(&sharun_dir, &interpreter, &launch_library_path,
+            &bin, &bin, &["--flag".into()], "libbar.so").unwrap()
+            .iter().map(|arg| arg.to_string_lossy().to_string()).collect();
+
+        let plan_json = get_plan_json(&initial_env, &interpreter, &launch_library_path, &argv, "userland_execve");
+        assert!(plan_json.contains("\"mode\": \"userland_execve\""));
+        assert!(plan_json.contains(&format!("\"interpreter\": {}", json_string(&interpreter.to_string_lossy()))));
+        assert!(plan_json.contains(&format!("\"library_path\": {}", json_string(&launch_library_path))));
+        let argv_json = [
+            json_string(&interpreter.to_string_lossy()),
+            json_string("--library-path"),
+            json_string(&launch_library_path),
+            json_string("--argv0"),
+            json_string(&bin),
+            json_string("--preload"),
+            json_string(&format!("{library_path}/foo/libfoo.so libbar.so")),
+            json_string(&bin),
+            json_string("--flag")
+        ].map(|arg| format!("    {arg}")).join(",\n");
+        assert!(plan_json.contains(&format!("\"argv\": [\n{argv_json}\n  ]")));
+        std::fs::remove_dir_all(dir).ok();
+    }
+}